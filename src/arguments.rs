@@ -0,0 +1,228 @@
+use rand::random;
+use game_application::{Settings, Mesher, CameraPose, Generator, DEFAULT_VIEW_DISTANCE, DEFAULT_HEIGHT_SCALE, DEFAULT_TERRAIN,
+                       NO_CAVES, DEFAULT_EROSION, NO_WATER, NO_FEATURES, OreParameters};
+
+
+const DEFAULT_RENDER_SIZE: (u32, u32) = (1280, 720);
+
+
+pub enum Command {
+    Play,
+    // Один кадр без окна в PNG
+    Render {
+        pose: CameraPose,
+        size: (u32, u32),
+        output: String
+    },
+    // Меш мира вокруг камеры в OBJ, PLY или glTF, формат по расширению файла
+    Export {
+        pose: CameraPose,
+        output: String
+    },
+    // Поле высот конечной карты в 16-битный PNG
+    Heightmap {
+        output: String
+    },
+    // Сколько вокселей каждого материала в мире вокруг камеры
+    Stats {
+        pose: CameraPose
+    }
+}
+
+
+pub struct Arguments {
+    pub directory: String,
+    pub settings: Settings,
+    pub command: Command
+}
+
+
+fn parse_value<T: ::std::str::FromStr>(name: &str, value: Option<&String>) -> Result<T, String> {
+    match value {
+        Some(value) => value.parse().map_err(|_| format!("Wrong value for {}: {}", name, value)),
+        None => Err(format!("Missing value for {}", name))
+    }
+}
+
+
+// Размер в формате "ширинаxвысота"
+fn parse_size(name: &str, value: Option<&String>) -> Result<(u32, u32), String> {
+    let value: String = parse_value(name, value)?;
+    let mut parts = value.split('x').map(|part| part.parse::<u32>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(width)), Some(Ok(height)), None) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("Wrong value for {}: {}", name, value))
+    }
+}
+
+
+impl Arguments {
+    pub fn parse(args: &[String]) -> Result<Arguments, String> {
+        let mut directory = None;
+        let mut seed = None;
+        let mut world_path = None;
+        let mut mesher = Mesher::MarchingCubes;
+        let mut infinite = false;
+        let mut view_distance = DEFAULT_VIEW_DISTANCE;
+        let mut show_statistics = false;
+        let mut lod = true;
+        let mut heightmap = None;
+        let mut height_scale = DEFAULT_HEIGHT_SCALE;
+        let mut generator = None;
+        let mut terrain = DEFAULT_TERRAIN;
+        let mut erosion = false;
+        let mut rivers_requested = false;
+        let mut droplets = None;
+        let mut thermal_iterations = None;
+        let mut pose = None;
+        let mut size = None;
+        let mut output = None;
+        let command_name = match args.get(1).map(|arg| arg.as_str()) {
+            Some(name @ "render") | Some(name @ "export") | Some(name @ "heightmap") | Some(name @ "stats") => Some(name),
+            _ => None
+        };
+        let mut iterator = args.iter().skip(if command_name.is_some() {2} else {1});
+        while let Some(arg) = iterator.next() {
+            match arg.as_str() {
+                "--camera" if command_name.is_some() && command_name != Some("heightmap") => pose = Some(parse_value(arg, iterator.next())?),
+                "--size" if command_name == Some("render") => size = Some(parse_size(arg, iterator.next())?),
+                "--out" if command_name.is_some() && command_name != Some("stats") => output = Some(parse_value(arg, iterator.next())?),
+                "--no-lod" => lod = false,
+                "--seed" => seed = Some(parse_value(arg, iterator.next())?),
+                "--world" => world_path = Some(parse_value(arg, iterator.next())?),
+                "--mesher" => mesher = parse_value(arg, iterator.next())?,
+                "--infinite" => infinite = true,
+                "--view-distance" => view_distance = parse_value(arg, iterator.next())?,
+                "--statistics" => show_statistics = true,
+                "--heightmap" => heightmap = Some(parse_value(arg, iterator.next())?),
+                "--min-height" => height_scale.min_height = parse_value(arg, iterator.next())?,
+                "--max-height" => height_scale.max_height = parse_value(arg, iterator.next())?,
+                "--generator" => generator = Some(parse_value(arg, iterator.next())?),
+                "--noise" => terrain.noise.basis = parse_value(arg, iterator.next())?,
+                "--fractal" => terrain.noise.fractal.kind = parse_value(arg, iterator.next())?,
+                "--octaves" => terrain.noise.fractal.octaves = parse_value(arg, iterator.next())?,
+                "--lacunarity" => terrain.noise.fractal.lacunarity = parse_value(arg, iterator.next())?,
+                "--gain" => terrain.noise.fractal.gain = parse_value(arg, iterator.next())?,
+                "--no-caves" => terrain.caves = NO_CAVES,
+                "--no-biomes" => terrain.biomes = false,
+                "--tunnels" => terrain.caves.tunnels = parse_value(arg, iterator.next())?,
+                "--caverns" => terrain.caves.caverns = parse_value(arg, iterator.next())?,
+                "--cave-size" => terrain.caves.size = parse_value(arg, iterator.next())?,
+                "--cave-min-depth" => terrain.caves.min_depth = parse_value(arg, iterator.next())?,
+                "--cave-max-depth" => terrain.caves.max_depth = parse_value(arg, iterator.next())?,
+                "--overhangs" => terrain.caves.overhangs = parse_value(arg, iterator.next())?,
+                "--erosion" => erosion = true,
+                "--droplets" => droplets = Some(parse_value(arg, iterator.next())?),
+                "--inertia" => terrain.erosion.inertia = parse_value(arg, iterator.next())?,
+                "--sediment-capacity" => terrain.erosion.capacity = parse_value(arg, iterator.next())?,
+                "--erosion-rate" => terrain.erosion.erosion_rate = parse_value(arg, iterator.next())?,
+                "--deposition-rate" => terrain.erosion.deposition_rate = parse_value(arg, iterator.next())?,
+                "--evaporation-rate" => terrain.erosion.evaporation_rate = parse_value(arg, iterator.next())?,
+                "--thermal-iterations" => thermal_iterations = Some(parse_value(arg, iterator.next())?),
+                "--talus" => terrain.erosion.talus = parse_value(arg, iterator.next())?,
+                "--thermal-rate" => terrain.erosion.thermal_rate = parse_value(arg, iterator.next())?,
+                "--no-water" => terrain.water = NO_WATER,
+                "--no-sea" => terrain.water.sea = false,
+                "--sea-level" => {
+                    terrain.water.sea = true;
+                    terrain.water.sea_level = Some(parse_value(arg, iterator.next())?);
+                },
+                "--no-lakes" => terrain.water.lakes = false,
+                "--rivers" => {
+                    rivers_requested = true;
+                    terrain.water.rivers = parse_value(arg, iterator.next())?;
+                },
+                "--river-width" => {
+                    rivers_requested = true;
+                    terrain.water.river_width = parse_value(arg, iterator.next())?;
+                },
+                "--river-depth" => {
+                    rivers_requested = true;
+                    terrain.water.river_depth = parse_value(arg, iterator.next())?;
+                },
+                "--no-features" => terrain.features = NO_FEATURES,
+                "--no-trees" => terrain.features.trees = false,
+                "--no-boulders" => terrain.features.boulders = false,
+                "--no-ruins" => terrain.features.ruins = false,
+                "--feature-spacing" => terrain.features.spacing = parse_value(arg, iterator.next())?,
+                "--no-ores" => {
+                    for ore in terrain.ores.iter_mut() {
+                        ore.rarity = 0.0;
+                    }
+                },
+                "--ore" => {
+                    let ore: OreParameters = parse_value(arg, iterator.next())?;
+                    terrain.ores[ore.kind as usize] = ore;
+                },
+                _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+                _ if directory.is_none() => directory = Some(arg.clone()),
+                _ => return Err("Wrong arguments number".to_string())
+            }
+        }
+        height_scale.validate()?;
+        // Эрозия заметно замедляет запуск, поэтому включается только явно: флагом --erosion или числом капель и проходов
+        if erosion {
+            terrain.erosion.droplets = DEFAULT_EROSION.droplets;
+            terrain.erosion.thermal_iterations = DEFAULT_EROSION.thermal_iterations;
+        }
+        terrain.erosion.droplets = droplets.unwrap_or(terrain.erosion.droplets);
+        terrain.erosion.thermal_iterations = thermal_iterations.unwrap_or(terrain.erosion.thermal_iterations);
+        terrain.validate()?;
+        // Озера и реки ищутся по полю высот всей карты, и у бесконечной карты есть только море
+        if infinite {
+            if rivers_requested && terrain.water.rivers > 0 {
+                return Err("Rivers need a finite map, they can't be used with --infinite".to_string());
+            }
+            terrain.water.lakes = false;
+            terrain.water.rivers = 0;
+        }
+        // Бесконечную карту умеет строить только шум
+        let generator = match generator {
+            Some(Generator::DiamondSquare) if infinite => return Err("Diamond-square can't generate an infinite map".to_string()),
+            Some(generator) => generator,
+            None if infinite => Generator::Noise,
+            None => Generator::DiamondSquare
+        };
+        let pose = pose.unwrap_or_else(|| "0,0,0,0,0".parse().unwrap());
+        let command = match command_name {
+            Some("render") => Command::Render {
+                pose,
+                size: size.unwrap_or(DEFAULT_RENDER_SIZE),
+                output: output.unwrap_or_else(|| "frame.png".to_string())
+            },
+            Some("heightmap") => Command::Heightmap {
+                output: output.unwrap_or_else(|| "heightmap.png".to_string())
+            },
+            Some("stats") => Command::Stats {
+                pose
+            },
+            Some(_) => {
+                // Экспортируется вся геометрия в полном разрешении
+                lod = false;
+                Command::Export {
+                    pose,
+                    output: output.unwrap_or_else(|| "terrain.obj".to_string())
+                }
+            },
+            None => Command::Play
+        };
+        Ok(Arguments {
+            directory: directory.unwrap_or_else(|| ".".to_string()),
+            settings: Settings {
+                // Без явного зерна мир случайный, но зерно печатается, чтобы его можно было воспроизвести
+                seed: seed.unwrap_or_else(random),
+                world_path,
+                mesher,
+                infinite,
+                view_distance,
+                show_statistics,
+                lod,
+                heightmap,
+                height_scale,
+                generator,
+                terrain
+            },
+            command
+        })
+    }
+}
@@ -0,0 +1,150 @@
+use game_application::noise::{Noise, SimplexNoise, Fractal, FractalKind};
+use game_application::material::{BlockId, GRASS, DIRT, STONE, SAND, SNOW, GRAVEL};
+use game_application::random::get_column_hash;
+
+
+// Размер климатических зон в вокселях
+const CLIMATE_SIZE: f64 = 256.0;
+const CLIMATE_FRACTAL: Fractal = Fractal {
+    kind: FractalKind::Fbm,
+    octaves: 3,
+    lacunarity: 2.0,
+    gain: 0.5
+};
+// Ширина перехода между биомами в единицах температуры и влажности
+const BLEND_WIDTH: f64 = 0.2;
+
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+    Tundra,
+    Ocean
+}
+
+
+struct BiomeParameters {
+    // Климат, при котором биом выражен сильнее всего, температура и влажность в [-1, 1]
+    temperature: f64,
+    humidity: f64,
+    // Высота относительно среднего уровня рельефа и размах неровностей
+    height_offset: f64,
+    amplitude: f64,
+    shape: FractalKind,
+    surface: BlockId,
+    subsurface: BlockId,
+    subsurface_depth: i32
+}
+
+
+// Порядок совпадает с вариантами Biome
+const BIOMES: [Biome; 5] = [Biome::Plains, Biome::Desert, Biome::Mountains, Biome::Tundra, Biome::Ocean];
+const BIOME_PARAMETERS: [BiomeParameters; 5] = [
+    BiomeParameters {temperature: 0.0, humidity: 0.1, height_offset: 0.0, amplitude: 12.0, shape: FractalKind::Fbm,
+                     surface: GRASS, subsurface: DIRT, subsurface_depth: 3},
+    BiomeParameters {temperature: 0.6, humidity: -0.5, height_offset: -2.0, amplitude: 8.0, shape: FractalKind::Billow,
+                     surface: SAND, subsurface: SAND, subsurface_depth: 4},
+    BiomeParameters {temperature: -0.3, humidity: -0.5, height_offset: 24.0, amplitude: 40.0, shape: FractalKind::Ridged,
+                     surface: STONE, subsurface: STONE, subsurface_depth: 0},
+    BiomeParameters {temperature: -0.7, humidity: 0.3, height_offset: 4.0, amplitude: 16.0, shape: FractalKind::Fbm,
+                     surface: SNOW, subsurface: DIRT, subsurface_depth: 2},
+    BiomeParameters {temperature: 0.2, humidity: 0.7, height_offset: -28.0, amplitude: 10.0, shape: FractalKind::Fbm,
+                     surface: SAND, subsurface: GRAVEL, subsurface_depth: 3}
+];
+
+
+impl Biome {
+    fn get_parameters(&self) -> &'static BiomeParameters {
+        &BIOME_PARAMETERS[*self as usize]
+    }
+
+    // depth - расстояние от поверхности столбца
+    pub fn get_material(&self, depth: i32) -> BlockId {
+        let parameters = self.get_parameters();
+        if depth == 0 {
+            parameters.surface
+        } else if depth <= parameters.subsurface_depth {
+            parameters.subsurface
+        } else {
+            STONE
+        }
+    }
+}
+
+
+// Карта биомов по двум независимым шумам температуры и влажности
+pub struct BiomeMap {
+    temperature: SimplexNoise,
+    humidity: SimplexNoise,
+    seed: u64
+}
+
+
+impl BiomeMap {
+    pub fn new(seed: u64) -> BiomeMap {
+        BiomeMap {
+            temperature: SimplexNoise::new(seed ^ 0x7E3A_91C5),
+            humidity: SimplexNoise::new(seed ^ 0x1B87_3593),
+            seed
+        }
+    }
+
+    fn get_climate(&self, noise: &SimplexNoise, x: i32, y: i32) -> f64 {
+        let (x, y) = (x as f64 / CLIMATE_SIZE, y as f64 / CLIMATE_SIZE);
+        // Сумма октав редко отходит от нуля дальше 0.5, поэтому растягивается на весь отрезок
+        (2.0 * CLIMATE_FRACTAL.get(|frequency| noise.get(x * frequency, y * frequency))).clamp(-1.0, 1.0)
+    }
+
+    // Вес каждого биома в столбце, в сумме единица. Вдали от границ почти весь вес у одного биома
+    fn get_weights(&self, x: i32, y: i32) -> [f64; 5] {
+        let temperature = self.get_climate(&self.temperature, x, y);
+        let humidity = self.get_climate(&self.humidity, x, y);
+        let mut weights = [0.0; 5];
+        for (weight, parameters) in weights.iter_mut().zip(BIOME_PARAMETERS.iter()) {
+            let distance_squared = (temperature - parameters.temperature).powi(2) + (humidity - parameters.humidity).powi(2);
+            *weight = (-distance_squared / (BLEND_WIDTH * BLEND_WIDTH)).exp();
+        }
+        let total: f64 = weights.iter().sum();
+        for weight in weights.iter_mut() {
+            *weight /= total;
+        }
+        weights
+    }
+
+    // Высота столбца: base - средний уровень рельефа, value - рельеф в [-1, 1],
+    // каждый биом по-своему его растягивает, а результаты смешиваются по весам
+    pub fn get_height(&self, x: i32, y: i32, base: f64, value: f64) -> f64 {
+        self.get_weights(x, y).iter().zip(BIOME_PARAMETERS.iter())
+            .map(|(weight, parameters)| weight * (base + parameters.height_offset + parameters.amplitude * parameters.shape.apply(value)))
+            .sum()
+    }
+
+    // Материалы на границе перемешиваются: биом столбца выбирается случайно с вероятностью, равной его весу
+    pub fn get_biome(&self, x: i32, y: i32) -> Biome {
+        let mut choice = get_column_hash(x, y, self.seed);
+        for (biome, weight) in BIOMES.iter().zip(self.get_weights(x, y).iter()) {
+            if choice < *weight {
+                return *biome;
+            }
+            choice -= weight;
+        }
+        Biome::Plains
+    }
+
+    // Насколько выше base может оказаться поверхность
+    pub fn get_max_height_offset() -> f64 {
+        BIOME_PARAMETERS.iter().map(|parameters| parameters.height_offset + parameters.amplitude).fold(0.0, f64::max)
+    }
+
+    // Применяет биомы к готовому полю высот [y][x], например от алмаза-квадрата
+    pub fn shape_height_field(&self, height_field: &mut [Vec<f64>], base: f64, amplitude: f64) {
+        for (y, row) in height_field.iter_mut().enumerate() {
+            for (x, height) in row.iter_mut().enumerate() {
+                let value = ((*height - base) / amplitude).clamp(-1.0, 1.0);
+                *height = self.get_height(x as i32, y as i32, base, value);
+            }
+        }
+    }
+}
@@ -1,6 +1,35 @@
 use game_application::glutin;
-use game_application::cgmath::{Vector3, Point3};
+use game_application::cgmath::{Vector3, Point3, InnerSpace};
 use game_application::geometry::get_normalized;
+use std::str::FromStr;
+
+
+// Положение и направление взгляда камеры: углы в градусах, рыскание отсчитывается от оси z мира
+#[derive(Copy, Clone, Debug)]
+pub struct CameraPose {
+    pub position: Point3<f32>,
+    pub yaw: f32,
+    pub pitch: f32
+}
+
+
+impl FromStr for CameraPose {
+    type Err = String;
+
+    // Формат "x,y,z,yaw,pitch"
+    fn from_str(text: &str) -> Result<CameraPose, String> {
+        let values = text.split(',').map(|value| value.trim().parse::<f32>()).collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| format!("Wrong camera pose: {}", text))?;
+        if values.len() != 5 {
+            return Err(format!("Camera pose needs 5 values: {}", text));
+        }
+        Ok(CameraPose {
+            position: Point3::new(values[0], values[1], values[2]),
+            yaw: values[3],
+            pitch: values[4]
+        })
+    }
+}
 
 
 pub struct Camera {
@@ -12,7 +41,10 @@ pub struct Camera {
     cursor_position: (f32, f32),
     know_cursor: bool,
 
+    // Скорость полета в мировых координатах и наклона вбок за секунду
     move_speed: f32,
+    roll_speed: f32,
+    // Поворот взгляда на пиксель сдвига курсора
     rotate_speed: f32,
 
     moving_up: bool,
@@ -39,7 +71,8 @@ impl Camera {
             cursor_position: (0.0, 0.0),
             know_cursor: false,
 
-            move_speed: 0.01,
+            move_speed: 0.6,
+            roll_speed: 0.6,
             rotate_speed: 0.01,
 
             moving_up: false,
@@ -55,6 +88,20 @@ impl Camera {
         }
     }
 
+    pub fn from_pose(aspect_ratio: f32, pose: &CameraPose) -> Camera {
+        let mut camera = Camera::new(aspect_ratio, pose.position);
+        // При взгляде строго вверх или вниз направление вправо не определено
+        let (yaw, pitch) = (pose.yaw.to_radians(), pose.pitch.clamp(-89.0, 89.0).to_radians());
+        camera.forward_direction = Vector3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+        let right_direction = camera.forward_direction.cross(Vector3::new(0.0, 1.0, 0.0));
+        camera.up_direction = get_normalized(right_direction.cross(camera.forward_direction));
+        camera
+    }
+
+    pub fn get_direction(&self) -> Vector3<f32> {
+        self.forward_direction
+    }
+
     pub fn get_perspective(&self) -> [[f32; 4]; 4] {
         let fov: f32 = 3.141592 / 3.0;
         let zfar = 1024.0;
@@ -86,34 +133,40 @@ impl Camera {
         ]
     }
 
-    pub fn update(&mut self) {
-        let mut right_direction = self.forward_direction.cross(self.up_direction);
+    // Свободный полет сквозь рельеф
+    pub fn update(&mut self, elapsed: f32) {
+        let right_direction = self.forward_direction.cross(self.up_direction);
+        let distance = self.move_speed * elapsed;
 
         if self.moving_up {
-            self.position += self.move_speed * self.up_direction;
+            self.position += distance * self.up_direction;
         }
         if self.moving_down {
-            self.position -= self.move_speed * self.up_direction;
+            self.position -= distance * self.up_direction;
         }
         if self.moving_left {
-            self.position -= self.move_speed * right_direction;
+            self.position -= distance * right_direction;
         }
         if self.moving_right {
-            self.position += self.move_speed * right_direction;
+            self.position += distance * right_direction;
         }
         if self.moving_forward {
-            self.position += self.move_speed * self.forward_direction;
+            self.position += distance * self.forward_direction;
         }
         if self.moving_backward {
-            self.position -= self.move_speed * self.forward_direction;
+            self.position -= distance * self.forward_direction;
         }
+        self.rotate(elapsed);
+    }
 
+    pub fn rotate(&mut self, elapsed: f32) {
+        let mut right_direction = self.forward_direction.cross(self.up_direction);
         if self.rotate_clockwise {
-            self.up_direction += self.rotate_speed * right_direction;
+            self.up_direction += self.roll_speed * elapsed * right_direction;
             self.up_direction = get_normalized(self.up_direction);
         }
         if self.rotate_counterclockwise {
-            self.up_direction -= self.rotate_speed * right_direction;
+            self.up_direction -= self.roll_speed * elapsed * right_direction;
             self.up_direction = get_normalized(self.up_direction);
         }
 
@@ -128,6 +181,33 @@ impl Camera {
         self.cursor_move = (0.0, 0.0);
     }
 
+    // Направление ходьбы в горизонтальной плоскости мира, длина 1 или 0, и нажаты ли прыжок и приседание
+    pub fn get_walk_input(&self) -> (Vector3<f32>, bool, bool) {
+        let world_up = Vector3::new(0.0, 1.0, 0.0);
+        let forward = Vector3::new(self.forward_direction.x, 0.0, self.forward_direction.z);
+        let mut direction = Vector3::new(0.0, 0.0, 0.0);
+        if forward.magnitude2() > 1.0e-6 {
+            let forward = get_normalized(forward);
+            let right = forward.cross(world_up);
+            if self.moving_forward {
+                direction += forward;
+            }
+            if self.moving_backward {
+                direction -= forward;
+            }
+            if self.moving_right {
+                direction += right;
+            }
+            if self.moving_left {
+                direction -= right;
+            }
+        }
+        if direction.magnitude2() > 1.0e-6 {
+            direction = get_normalized(direction);
+        }
+        (direction, self.moving_up, self.moving_down)
+    }
+
     pub fn handle_event(&mut self, event: &glutin::WindowEvent) {
         if let glutin::WindowEvent::CursorMoved{position, ..} = *event {
             if self.know_cursor {
@@ -0,0 +1,294 @@
+use game_application::rand::Rng;
+use game_application::cgmath::Point3;
+use game_application::random::{get_rng, get_cell_seed};
+use game_application::noise::{Noise, SimplexNoise, Fractal, FractalKind};
+use game_application::chunk::{ChunkPosition, CHUNK_SIZE, get_chunk_origin};
+use std::f64::consts::PI;
+
+
+// Участок в вокселях по горизонтали, у которого свой набор тоннелей
+const TUNNEL_CELL: i32 = 64;
+// Длина тоннеля в шагах по одному вокселю
+const TUNNEL_LENGTH: usize = 96;
+const MAX_TUNNEL_RADIUS: f64 = 3.0;
+const MAX_TUNNEL_PITCH: f64 = 0.6;
+const CAVERN_SIZE: f64 = 24.0;
+const CAVERN_FRACTAL: Fractal = Fractal {
+    kind: FractalKind::Fbm,
+    octaves: 2,
+    lacunarity: 2.0,
+    gain: 0.5
+};
+const CAVERN_SPREAD: f64 = 0.55;
+// Размер выступов, который дает шум нависаний
+const OVERHANG_SIZE: f64 = 12.0;
+// Плотность нетронутого пещерами вокселя, больше любой плотности поверхности
+const SOLID: f64 = 1.0e9;
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct CaveParameters {
+    // Среднее число тоннелей на участок TUNNEL_CELL x TUNNEL_CELL
+    pub tunnels: f64,
+    // Примерная доля объема на глубине пещер, которую занимают большие полости
+    pub caverns: f64,
+    // Множитель размера тоннелей и полостей
+    pub size: f64,
+    // Глубина под поверхностью в вокселях, на которой встречаются пещеры
+    pub min_depth: f64,
+    pub max_depth: f64,
+    // Насколько вокселей трехмерный шум сдвигает поверхность, создавая нависающие скалы
+    pub overhangs: f64
+}
+
+
+pub const DEFAULT_CAVES: CaveParameters = CaveParameters {
+    tunnels: 1.0,
+    caverns: 0.03,
+    size: 1.0,
+    min_depth: 6.0,
+    max_depth: 64.0,
+    overhangs: 8.0
+};
+
+
+pub const NO_CAVES: CaveParameters = CaveParameters {
+    tunnels: 0.0,
+    caverns: 0.0,
+    size: 1.0,
+    min_depth: 0.0,
+    max_depth: 0.0,
+    overhangs: 0.0
+};
+
+
+impl CaveParameters {
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [self.tunnels, self.caverns, self.size, self.min_depth, self.max_depth, self.overhangs];
+        if values.iter().any(|value| !value.is_finite()) {
+            return Err("Cave parameters must be finite".to_string());
+        }
+        if self.tunnels < 0.0 || !(0.0..=1.0).contains(&self.caverns) || self.size <= 0.0 || self.overhangs < 0.0
+            || self.max_depth < self.min_depth {
+            return Err("Wrong cave parameters".to_string());
+        }
+        Ok(())
+    }
+}
+
+
+fn get_index(x: usize, y: usize, z: usize) -> usize {
+    x + CHUNK_SIZE * (y + CHUNK_SIZE * z)
+}
+
+
+// Плотность пещер для каждого вокселя чанка: отрицательная внутри пещеры
+pub struct CaveField {
+    densities: Vec<f64>
+}
+
+
+impl CaveField {
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.densities[get_index(x, y, z)]
+    }
+}
+
+
+pub struct CaveProbe<'caves> {
+    caves: &'caves Caves,
+    spheres: Vec<((f64, f64, f64), f64)>
+}
+
+
+impl<'caves> CaveProbe<'caves> {
+    // Вырезан ли воксель пещерой, так же как при заполнении чанка
+    pub fn is_carved(&self, x: i32, y: i32, z: i32, exact_height: f64) -> bool {
+        self.caves.get_cavern_density(x, y, z, exact_height - z as f64) < 0.0 || self.spheres.iter().any(|&(center, radius)| {
+            let (dx, dy, dz) = (x as f64 - center.0, y as f64 - center.1, z as f64 - center.2);
+            (dx * dx + dy * dy + dz * dz).sqrt() - radius < 0.0
+        })
+    }
+}
+
+
+// Трехмерная стадия построения рельефа поверх любого поля высот. Все считается от координат и зерна,
+// поэтому чанки по-прежнему строятся независимо друг от друга
+pub struct Caves {
+    parameters: CaveParameters,
+    seed: u64,
+    noise: SimplexNoise
+}
+
+
+impl Caves {
+    pub fn new(seed: u64, parameters: &CaveParameters) -> Caves {
+        Caves {
+            parameters: *parameters,
+            seed,
+            noise: SimplexNoise::new(seed ^ 0x5DEE_CE66)
+        }
+    }
+
+    // Насколько выше поля высот может подняться поверхность
+    pub fn get_max_overhang(&self) -> f64 {
+        self.parameters.overhangs
+    }
+
+    // Сдвиг высоты поверхности в точке. Он меняется с высотой, поэтому поверхность может нависать
+    pub fn get_overhang(&self, x: i32, y: i32, z: i32, exact_height: f64) -> f64 {
+        let amplitude = self.parameters.overhangs;
+        if amplitude <= 0.0 || (exact_height - z as f64).abs() > amplitude + 1.0 {
+            return 0.0;
+        }
+        amplitude * self.noise.get_3d(x as f64 / OVERHANG_SIZE, y as f64 / OVERHANG_SIZE, z as f64 / OVERHANG_SIZE)
+    }
+
+    pub fn get_surface_height(&self, x: i32, y: i32, z: i32, exact_height: f64) -> f64 {
+        exact_height + self.get_overhang(x, y, z, exact_height)
+    }
+
+    // Верхний заполненный рельефом воксель столбца ниже z_limit, без учета пещер. Из-за нависаний
+    // он может оказаться выше поля высот, а под выступом остаться воздух
+    pub fn get_top_voxel(&self, x: i32, y: i32, exact_height: f64, z_limit: i32) -> i32 {
+        let mut z = ((exact_height + self.parameters.overhangs.max(0.0)).round() as i32).min(z_limit - 1);
+        // Глубже размаха нависаний сдвиг нулевой, так что цикл всегда заканчивается
+        while (self.get_surface_height(x, y, z, exact_height).round() as i32) < z {
+            z -= 1;
+        }
+        z
+    }
+
+    // Вне диапазона глубин плотность растет плавно, чтобы у пещер не было плоских стен
+    fn get_depth_penalty(&self, depth: f64) -> f64 {
+        (self.parameters.min_depth - depth).max(0.0) + (depth - self.parameters.max_depth).max(0.0)
+    }
+
+    fn get_cavern_density(&self, x: i32, y: i32, z: i32, depth: f64) -> f64 {
+        let penalty = self.get_depth_penalty(depth);
+        if self.parameters.caverns <= 0.0 || penalty > 1.0 {
+            return SOLID;
+        }
+        // Полости сплюснуты по вертикали
+        let size = CAVERN_SIZE * self.parameters.size;
+        let (x, y, z) = (x as f64 / size, y as f64 / size, 2.0 * z as f64 / size);
+        // Значения двух октав распределены почти нормально, и линейный порог дает примерно долю caverns объема
+        let threshold = CAVERN_SPREAD * (1.0 - 2.0 * self.parameters.caverns);
+        let value = CAVERN_FRACTAL.get(|frequency| self.noise.get_3d(x * frequency + 100.0, y * frequency, z * frequency));
+        (threshold - value) * size / 2.0 + penalty
+    }
+
+    pub fn get_field<F: Fn(i32, i32) -> Option<f64>>(&self, position: &ChunkPosition, get_height: &F) -> CaveField {
+        let origin = get_chunk_origin(position);
+        let mut densities = vec![SOLID; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let (world_x, world_y) = (origin.x + x as i32, origin.y + y as i32);
+                let exact_height = match get_height(world_x, world_y) {
+                    Some(height) => height,
+                    None => continue
+                };
+                for z in 0..CHUNK_SIZE {
+                    let world_z = origin.z + z as i32;
+                    densities[get_index(x, y, z)] = self.get_cavern_density(world_x, world_y, world_z, exact_height - world_z as f64);
+                }
+            }
+        }
+        self.carve_tunnels(position, get_height, &mut densities);
+        CaveField {
+            densities
+        }
+    }
+
+    // Тоннели - "черви Перлина": шар, который ползет от случайной точки на нужной глубине,
+    // а его направление плавно меняется по шуму. Червь не уходит дальше TUNNEL_LENGTH от начала,
+    // поэтому для столбцов в прямоугольнике [min, max) достаточно перебрать ближайшие участки.
+    // visit получает центр и радиус каждого шага червя
+    fn trace_tunnels<F, V>(&self, min: (i32, i32), max: (i32, i32), get_height: &F, mut visit: V)
+        where F: Fn(i32, i32) -> Option<f64>, V: FnMut((f64, f64, f64), f64) {
+        if self.parameters.tunnels <= 0.0 {
+            return;
+        }
+        let max_radius = MAX_TUNNEL_RADIUS * self.parameters.size;
+        let reach = TUNNEL_LENGTH as i32 + max_radius.ceil() as i32;
+        let get_cells = |min: i32, max: i32| (min - reach).div_euclid(TUNNEL_CELL)..((max + reach).div_euclid(TUNNEL_CELL) + 1);
+        for cell_x in get_cells(min.0, max.0) {
+            for cell_y in get_cells(min.1, max.1) {
+                let mut rng = get_rng(get_cell_seed(self.seed, cell_x, cell_y));
+                let mut count = self.parameters.tunnels.floor() as usize;
+                if rng.gen::<f64>() < self.parameters.tunnels.fract() {
+                    count += 1;
+                }
+                for tunnel in 0..count {
+                    let x = (cell_x * TUNNEL_CELL) as f64 + rng.gen::<f64>() * TUNNEL_CELL as f64;
+                    let y = (cell_y * TUNNEL_CELL) as f64 + rng.gen::<f64>() * TUNNEL_CELL as f64;
+                    let depth = self.parameters.min_depth + rng.gen::<f64>() * (self.parameters.max_depth - self.parameters.min_depth).max(0.0);
+                    let yaw = rng.gen::<f64>() * 2.0 * PI;
+                    let radius = max_radius * (0.5 + 0.5 * rng.gen::<f64>());
+                    let noise_offset = tunnel as f64 * 17.0 + rng.gen::<f64>() * 1000.0;
+                    let surface = match get_height(x.floor() as i32, y.floor() as i32) {
+                        Some(height) => height,
+                        None => continue
+                    };
+                    self.trace_tunnel((x, y, surface - depth), yaw, radius, noise_offset, &mut visit);
+                }
+            }
+        }
+    }
+
+    fn trace_tunnel<V: FnMut((f64, f64, f64), f64)>(&self, start: (f64, f64, f64), mut yaw: f64, radius: f64, noise_offset: f64,
+                                                      visit: &mut V) {
+        let (mut x, mut y, mut z) = start;
+        let mut pitch = 0.0;
+        for step in 0..TUNNEL_LENGTH {
+            // К концам тоннель сужается
+            visit((x, y, z), radius * (0.4 + 0.6 * (PI * step as f64 / TUNNEL_LENGTH as f64).sin()));
+            let t = step as f64 * 0.05;
+            yaw += 0.3 * self.noise.get(t, noise_offset);
+            pitch = (0.9 * pitch + 0.2 * self.noise.get(t, noise_offset + 500.0)).clamp(-MAX_TUNNEL_PITCH, MAX_TUNNEL_PITCH);
+            x += pitch.cos() * yaw.cos();
+            y += pitch.cos() * yaw.sin();
+            z += pitch.sin();
+        }
+    }
+
+    fn carve_tunnels<F: Fn(i32, i32) -> Option<f64>>(&self, position: &ChunkPosition, get_height: &F, densities: &mut [f64]) {
+        let origin = get_chunk_origin(position);
+        let size = CHUNK_SIZE as i32;
+        self.trace_tunnels((origin.x, origin.y), (origin.x + size, origin.y + size), get_height,
+                           |center, radius| self.carve_sphere(&origin, center, radius, densities));
+    }
+
+    // Проверка отдельных вокселей в прямоугольнике [min, max) без построения всего чанка.
+    // Тоннели прокладываются один раз, а каждая проверка только сравнивает расстояния до их шагов
+    pub fn get_probe<F: Fn(i32, i32) -> Option<f64>>(&self, min: (i32, i32), max: (i32, i32), get_height: &F) -> CaveProbe<'_> {
+        let mut spheres = Vec::new();
+        self.trace_tunnels(min, max, get_height, |center, radius| {
+            let overlaps = |center: f64, min: i32, max: i32| center + radius >= min as f64 && center - radius <= max as f64;
+            if overlaps(center.0, min.0, max.0) && overlaps(center.1, min.1, max.1) {
+                spheres.push((center, radius));
+            }
+        });
+        CaveProbe {
+            caves: self,
+            spheres
+        }
+    }
+
+    fn carve_sphere(&self, origin: &Point3<i32>, center: (f64, f64, f64), radius: f64, densities: &mut [f64]) {
+        let get_range = |center: f64, origin: i32| {
+            let from = ((center - radius).floor() as i32 - origin).max(0);
+            let to = ((center + radius).ceil() as i32 - origin + 1).min(CHUNK_SIZE as i32);
+            from..to
+        };
+        for x in get_range(center.0, origin.x) {
+            for y in get_range(center.1, origin.y) {
+                for z in get_range(center.2, origin.z) {
+                    let (dx, dy, dz) = ((origin.x + x) as f64 - center.0, (origin.y + y) as f64 - center.1, (origin.z + z) as f64 - center.2);
+                    let density = &mut densities[get_index(x as usize, y as usize, z as usize)];
+                    *density = density.min((dx * dx + dy * dy + dz * dz).sqrt() - radius);
+                }
+            }
+        }
+    }
+}
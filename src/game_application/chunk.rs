@@ -0,0 +1,161 @@
+use game_application::cgmath::Point3;
+use game_application::material::{BlockId, AIR};
+
+
+pub const CHUNK_SIZE: usize = 32;
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+// Плотность хранится в i8: положительная внутри твердого материала, отрицательная снаружи
+const DENSITY_SCALE: f32 = 127.0;
+
+// Окрестность хранит чанк вместе с одним слоем соседних вокселей с каждой стороны
+const NEIGHBORHOOD_SIZE: usize = CHUNK_SIZE + 2;
+
+pub type ChunkPosition = Point3<i32>;
+
+
+fn get_index(x: usize, y: usize, z: usize) -> usize {
+    x + CHUNK_SIZE * (y + CHUNK_SIZE * z)
+}
+
+
+pub fn get_chunk_position(x: i32, y: i32, z: i32) -> (ChunkPosition, Point3<usize>) {
+    let size = CHUNK_SIZE as i32;
+    (
+        Point3::new(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size)),
+        Point3::new(x.rem_euclid(size) as usize, y.rem_euclid(size) as usize, z.rem_euclid(size) as usize)
+    )
+}
+
+
+pub fn get_chunk_origin(position: &ChunkPosition) -> Point3<i32> {
+    let size = CHUNK_SIZE as i32;
+    Point3::new(position.x * size, position.y * size, position.z * size)
+}
+
+
+fn get_default_density(value: BlockId) -> f32 {
+    if value.is_solid() {1.0} else {-1.0}
+}
+
+
+pub struct Chunk {
+    voxels: Vec<BlockId>,
+    densities: Vec<i8>
+}
+
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        Chunk {
+            voxels: vec![AIR; CHUNK_VOLUME],
+            densities: vec![-DENSITY_SCALE as i8; CHUNK_VOLUME]
+        }
+    }
+
+    pub fn from_data(voxels: Vec<BlockId>, densities: Vec<i8>) -> Option<Chunk> {
+        if voxels.len() != CHUNK_VOLUME || densities.len() != CHUNK_VOLUME {
+            return None;
+        }
+        Some(Chunk {
+            voxels,
+            densities
+        })
+    }
+
+    pub fn get_voxel(&self, x: usize, y: usize, z: usize) -> BlockId {
+        self.voxels[get_index(x, y, z)]
+    }
+
+    pub fn get_voxels(&self) -> &[BlockId] {
+        &self.voxels
+    }
+
+    pub fn get_densities(&self) -> &[i8] {
+        &self.densities
+    }
+
+    // Меняет материал и сбрасывает плотность в значение по умолчанию для него
+    pub fn set_voxel(&mut self, x: usize, y: usize, z: usize, value: BlockId) {
+        self.voxels[get_index(x, y, z)] = value;
+        self.set_density(x, y, z, get_default_density(value));
+    }
+
+    pub fn get_density(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.densities[get_index(x, y, z)] as f32 / DENSITY_SCALE
+    }
+
+    pub fn set_density(&mut self, x: usize, y: usize, z: usize, density: f32) {
+        self.densities[get_index(x, y, z)] = (density.clamp(-1.0, 1.0) * DENSITY_SCALE).round() as i8;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.iter().all(|voxel| *voxel == AIR)
+    }
+}
+
+
+pub struct Neighborhood {
+    pub position: ChunkPosition,
+    voxels: Vec<BlockId>,
+    densities: Vec<f32>
+}
+
+
+impl Neighborhood {
+    pub fn new(position: ChunkPosition) -> Neighborhood {
+        Neighborhood {
+            position,
+            voxels: vec![AIR; NEIGHBORHOOD_SIZE * NEIGHBORHOOD_SIZE * NEIGHBORHOOD_SIZE],
+            densities: vec![-1.0; NEIGHBORHOOD_SIZE * NEIGHBORHOOD_SIZE * NEIGHBORHOOD_SIZE]
+        }
+    }
+
+    fn get_index(x: i32, y: i32, z: i32) -> usize {
+        let size = NEIGHBORHOOD_SIZE as i32;
+        ((x + 1) + size * ((y + 1) + size * (z + 1))) as usize
+    }
+
+    // Координаты локальные относительно чанка, от -1 до CHUNK_SIZE включительно
+    pub fn get_voxel(&self, x: i32, y: i32, z: i32) -> BlockId {
+        self.voxels[Neighborhood::get_index(x, y, z)]
+    }
+
+    pub fn get_density(&self, x: i32, y: i32, z: i32) -> f32 {
+        self.densities[Neighborhood::get_index(x, y, z)]
+    }
+
+    pub fn set_voxel(&mut self, x: i32, y: i32, z: i32, value: BlockId, density: f32) {
+        let index = Neighborhood::get_index(x, y, z);
+        self.voxels[index] = value;
+        self.densities[index] = density;
+    }
+
+    pub fn get_origin(&self) -> Point3<i32> {
+        get_chunk_origin(&self.position)
+    }
+
+    // Копирует в окрестность пересечение с соседним чанком, сдвинутым на offset
+    pub fn copy_from(&mut self, chunk: &Chunk, offset: (i32, i32, i32)) {
+        let range = |offset: i32| -> (usize, usize) {
+            match offset {
+                -1 => (CHUNK_SIZE - 1, CHUNK_SIZE),
+                0 => (0, CHUNK_SIZE),
+                _ => (0, 1)
+            }
+        };
+        let size = CHUNK_SIZE as i32;
+        let (x_range, y_range, z_range) = (range(offset.0), range(offset.1), range(offset.2));
+        for z in z_range.0..z_range.1 {
+            for y in y_range.0..y_range.1 {
+                for x in x_range.0..x_range.1 {
+                    self.set_voxel(offset.0 * size + x as i32,
+                                   offset.1 * size + y as i32,
+                                   offset.2 * size + z as i32,
+                                   chunk.get_voxel(x, y, z),
+                                   chunk.get_density(x, y, z));
+                }
+            }
+        }
+    }
+}
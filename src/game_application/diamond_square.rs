@@ -1,4 +1,5 @@
-use game_application::rand::{Rng, thread_rng};
+use game_application::rand::Rng;
+use game_application::random::get_rng;
 
 
 fn get_delta(random: f64, roughness: f64, square_size: usize) -> f64 {
@@ -6,8 +7,8 @@ fn get_delta(random: f64, roughness: f64, square_size: usize) -> f64 {
 }
 
 
-pub fn generate_hight_map(size: usize) -> Vec<Vec<usize>> {
-    let mut rng = thread_rng();
+pub fn generate_height_field(size: usize, seed: u64) -> Vec<Vec<f64>> {
+    let mut rng = get_rng(seed);
     let mut result = Vec::with_capacity(size);
     for i in 0..size {
         result.push(Vec::with_capacity(size));
@@ -84,12 +85,43 @@ pub fn generate_hight_map(size: usize) -> Vec<Vec<usize>> {
         square_size = middle;
     }
 
-    let mut rounded_result = Vec::new();
-    for i in 0..size {
-        rounded_result.push(Vec::new());
-        for j in 0..size {
-            rounded_result[i].push((result[i][j]).round() as usize);
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use game_application::cgmath::Point3;
+    use game_application::diamond_square::generate_height_field;
+    use game_application::map::Map;
+    use game_application::terrain::{HeightFieldTerrain, DEFAULT_TERRAIN};
+
+    const MAP_SIZE: usize = 65;
+
+    fn get_voxels(seed: u64) -> Vec<u8> {
+        let mut map = Map::new(1.0, MAP_SIZE, Point3::new(0.0, 0.0, 0.0));
+        map.build_terrain(seed, &HeightFieldTerrain::new(generate_height_field(MAP_SIZE, seed), MAP_SIZE, seed, &DEFAULT_TERRAIN));
+        let size = MAP_SIZE as i32;
+        let mut voxels = Vec::new();
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    voxels.push(map.get_voxel(x, y, z).0);
+                }
+            }
         }
+        voxels
+    }
+
+    fn get_height_bits(seed: u64) -> Vec<u64> {
+        generate_height_field(MAP_SIZE, seed).iter().flat_map(|row| row.iter().map(|height| height.to_bits())).collect()
+    }
+
+    #[test]
+    fn test_same_seed_generates_identically() {
+        assert!(get_height_bits(42) == get_height_bits(42));
+        assert!(get_height_bits(42) != get_height_bits(43));
+        assert!(get_voxels(42) == get_voxels(42));
+        assert!(get_voxels(42) != get_voxels(43));
     }
-    rounded_result
 }
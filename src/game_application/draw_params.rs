@@ -1,11 +1,27 @@
 use game_application::vertex::Vertex;
+use game_application::chunk::ChunkPosition;
 
 
 pub struct UniformsStruct {
 }
 
 
+pub enum ChunkChange {
+    Updated(ChunkPosition, Vec<Vertex>, Vec<u32>),
+    Removed(ChunkPosition)
+}
+
+
+// Сколько чанков отрисовано и отброшено отсечением по пирамиде видимости за кадр
+#[derive(Copy, Clone, Default, Debug)]
+pub struct DrawStatistics {
+    pub draw_calls: usize,
+    pub triangles: usize,
+    pub culled_chunks: usize
+}
+
+
 pub struct DrawParams {
-    pub shape: Vec<Vertex>,
+    pub changes: Vec<ChunkChange>,
     pub uniforms: UniformsStruct
 }
@@ -0,0 +1,280 @@
+use game_application::rand::Rng;
+use game_application::random::get_rng;
+
+
+// Капля, которая так и не стекла в низину, испаряется через столько шагов
+const MAX_DROPLET_STEPS: usize = 64;
+// Радиус кисти, которой капля размывает склон: так не остается ям в один столбец
+const BRUSH_RADIUS: i32 = 2;
+const GRAVITY: f64 = 4.0;
+// Даже на пологом склоне капля может унести немного осадка
+const MIN_CAPACITY: f64 = 0.01;
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct ErosionParameters {
+    // Гидравлическая эрозия: число капель
+    pub droplets: usize,
+    // Насколько капля сохраняет направление, а не стекает строго по склону, в [0, 1]
+    pub inertia: f64,
+    // Сколько осадка капля может нести на единицу скорости, воды и перепада высот
+    pub capacity: f64,
+    // Доли недостающего и лишнего осадка, которые капля за шаг забирает у склона и оставляет на нем
+    pub erosion_rate: f64,
+    pub deposition_rate: f64,
+    // Доля воды, испаряющейся за шаг
+    pub evaporation_rate: f64,
+    // Термическая эрозия: число проходов
+    pub thermal_iterations: usize,
+    // Наибольший устойчивый перепад высот между соседними столбцами, круче материал осыпается
+    pub talus: f64,
+    // Доля лишнего перепада, которая осыпается за проход
+    pub thermal_rate: f64
+}
+
+
+pub const DEFAULT_EROSION: ErosionParameters = ErosionParameters {
+    droplets: 50000,
+    inertia: 0.05,
+    capacity: 4.0,
+    erosion_rate: 0.3,
+    deposition_rate: 0.3,
+    evaporation_rate: 0.02,
+    thermal_iterations: 20,
+    talus: 1.5,
+    thermal_rate: 0.5
+};
+
+
+pub const NO_EROSION: ErosionParameters = ErosionParameters {
+    droplets: 0,
+    thermal_iterations: 0,
+    .. DEFAULT_EROSION
+};
+
+
+impl ErosionParameters {
+    pub fn is_enabled(&self) -> bool {
+        self.droplets > 0 || self.thermal_iterations > 0
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // В файле мира счетчики хранятся как u32
+        if self.droplets > u32::MAX as usize || self.thermal_iterations > u32::MAX as usize {
+            return Err("Too many erosion droplets or iterations".to_string());
+        }
+        let rates = [self.inertia, self.erosion_rate, self.deposition_rate, self.evaporation_rate, self.thermal_rate];
+        if rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) || self.capacity < 0.0 || self.talus < 0.0 {
+            return Err("Wrong erosion parameters".to_string());
+        }
+        Ok(())
+    }
+}
+
+
+// Поле высот [y][x] с билинейной интерполяцией между узлами
+struct HeightField<'field> {
+    heights: &'field mut [Vec<f64>],
+    width: usize,
+    length: usize
+}
+
+
+impl<'field> HeightField<'field> {
+    fn new(heights: &'field mut [Vec<f64>]) -> HeightField<'field> {
+        let width = heights.first().map_or(0, |row| row.len());
+        let length = heights.len();
+        HeightField {
+            heights,
+            width,
+            length
+        }
+    }
+
+    // Точка должна лежать внутри последней ячейки, чтобы у нее были все четыре угла
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= 0.0 && y >= 0.0 && x < (self.width - 1) as f64 && y < (self.length - 1) as f64
+    }
+
+    // Высота и градиент в точке внутри ячейки
+    fn get_height_and_gradient(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        let (cell_x, cell_y) = (x.floor() as usize, y.floor() as usize);
+        let (u, v) = (x - cell_x as f64, y - cell_y as f64);
+        let h00 = self.heights[cell_y][cell_x];
+        let h10 = self.heights[cell_y][cell_x + 1];
+        let h01 = self.heights[cell_y + 1][cell_x];
+        let h11 = self.heights[cell_y + 1][cell_x + 1];
+        let gradient_x = (h10 - h00) * (1.0 - v) + (h11 - h01) * v;
+        let gradient_y = (h01 - h00) * (1.0 - u) + (h11 - h10) * u;
+        let height = h00 * (1.0 - u) * (1.0 - v) + h10 * u * (1.0 - v) + h01 * (1.0 - u) * v + h11 * u * v;
+        (height, gradient_x, gradient_y)
+    }
+
+    // Осадок ложится на четыре угла ячейки пропорционально близости к ним
+    fn deposit(&mut self, x: f64, y: f64, amount: f64) {
+        let (cell_x, cell_y) = (x.floor() as usize, y.floor() as usize);
+        let (u, v) = (x - cell_x as f64, y - cell_y as f64);
+        self.heights[cell_y][cell_x] += amount * (1.0 - u) * (1.0 - v);
+        self.heights[cell_y][cell_x + 1] += amount * u * (1.0 - v);
+        self.heights[cell_y + 1][cell_x] += amount * (1.0 - u) * v;
+        self.heights[cell_y + 1][cell_x + 1] += amount * u * v;
+    }
+
+    // Снимает amount со столбцов в радиусе кисти, ближние теряют больше. Возвращает, сколько снято
+    fn erode(&mut self, x: f64, y: f64, amount: f64) -> f64 {
+        let (center_x, center_y) = (x.floor() as i32, y.floor() as i32);
+        let mut cells = Vec::new();
+        let mut total_weight = 0.0;
+        for dy in -BRUSH_RADIUS..(BRUSH_RADIUS + 1) {
+            for dx in -BRUSH_RADIUS..(BRUSH_RADIUS + 1) {
+                let (cell_x, cell_y) = (center_x + dx, center_y + dy);
+                if cell_x < 0 || cell_y < 0 || cell_x >= self.width as i32 || cell_y >= self.length as i32 {
+                    continue;
+                }
+                let distance = ((cell_x as f64 - x).powi(2) + (cell_y as f64 - y).powi(2)).sqrt();
+                let weight = (BRUSH_RADIUS as f64 - distance).max(0.0);
+                if weight > 0.0 {
+                    cells.push((cell_x as usize, cell_y as usize, weight));
+                    total_weight += weight;
+                }
+            }
+        }
+        let mut eroded = 0.0;
+        for (cell_x, cell_y, weight) in cells {
+            let height = &mut self.heights[cell_y][cell_x];
+            let delta = (amount * weight / total_weight).min(*height);
+            *height -= delta;
+            eroded += delta;
+        }
+        eroded
+    }
+}
+
+
+// Капли падают в случайные точки и стекают по склону: на спуске разгоняются и забирают материал,
+// а замедляясь или попадая в яму, оставляют осадок
+fn erode_hydraulic(field: &mut HeightField, parameters: &ErosionParameters, seed: u64) {
+    let mut rng = get_rng(seed ^ 0x4E2F_0A1D);
+    for _ in 0..parameters.droplets {
+        let mut x = rng.gen::<f64>() * (field.width - 1) as f64;
+        let mut y = rng.gen::<f64>() * (field.length - 1) as f64;
+        let (mut direction_x, mut direction_y) = (0.0, 0.0);
+        let mut speed = 1.0;
+        let mut water = 1.0;
+        let mut sediment = 0.0;
+        for _ in 0..MAX_DROPLET_STEPS {
+            let (height, gradient_x, gradient_y) = field.get_height_and_gradient(x, y);
+            direction_x = direction_x * parameters.inertia - gradient_x * (1.0 - parameters.inertia);
+            direction_y = direction_y * parameters.inertia - gradient_y * (1.0 - parameters.inertia);
+            let length = (direction_x * direction_x + direction_y * direction_y).sqrt();
+            if length < 1.0e-9 {
+                break;
+            }
+            direction_x /= length;
+            direction_y /= length;
+            let (old_x, old_y) = (x, y);
+            x += direction_x;
+            y += direction_y;
+            if !field.contains(x, y) {
+                break;
+            }
+
+            let delta = field.get_height_and_gradient(x, y).0 - height;
+            let capacity = (-delta * speed * water * parameters.capacity).max(MIN_CAPACITY);
+            if delta > 0.0 || sediment > capacity {
+                // В яме осадок заполняет ее, но не выше точки, откуда капля пришла
+                let amount = if delta > 0.0 {delta.min(sediment)} else {(sediment - capacity) * parameters.deposition_rate};
+                sediment -= amount;
+                field.deposit(old_x, old_y, amount);
+            } else {
+                let amount = ((capacity - sediment) * parameters.erosion_rate).min(-delta);
+                sediment += field.erode(old_x, old_y, amount);
+            }
+            speed = (speed * speed - delta * GRAVITY).max(0.0).sqrt();
+            water *= 1.0 - parameters.evaporation_rate;
+        }
+    }
+}
+
+
+// Осыпание: материал со слишком крутых склонов сползает к более низким соседям
+fn erode_thermal(field: &mut HeightField, parameters: &ErosionParameters) {
+    let neighbors = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    for _ in 0..parameters.thermal_iterations {
+        // Изменения копятся отдельно, иначе результат зависел бы от порядка обхода
+        let mut changes = vec![vec![0.0; field.width]; field.length];
+        for y in 0..field.length {
+            for x in 0..field.width {
+                let height = field.heights[y][x];
+                let mut excesses = Vec::new();
+                let mut total_excess = 0.0;
+                let mut max_excess: f64 = 0.0;
+                for &(dx, dy) in &neighbors {
+                    let (neighbor_x, neighbor_y) = (x as i32 + dx, y as i32 + dy);
+                    if neighbor_x < 0 || neighbor_y < 0 || neighbor_x >= field.width as i32 || neighbor_y >= field.length as i32 {
+                        continue;
+                    }
+                    let excess = height - field.heights[neighbor_y as usize][neighbor_x as usize] - parameters.talus;
+                    if excess > 0.0 {
+                        excesses.push((neighbor_x as usize, neighbor_y as usize, excess));
+                        total_excess += excess;
+                        max_excess = max_excess.max(excess);
+                    }
+                }
+                // Половина перепада выравнивает пару столбцов, больше сдвигать нельзя, иначе склон перевернется
+                let amount = parameters.thermal_rate * max_excess / 2.0;
+                for (neighbor_x, neighbor_y, excess) in excesses {
+                    changes[neighbor_y][neighbor_x] += amount * excess / total_excess;
+                }
+                changes[y][x] -= amount;
+            }
+        }
+        for (row, row_changes) in field.heights.iter_mut().zip(changes.iter()) {
+            for (height, change) in row.iter_mut().zip(row_changes.iter()) {
+                *height += change;
+            }
+        }
+    }
+}
+
+
+// Обрабатывает поле высот [y][x] до того, как по нему построятся воксели. Результат зависит только от зерна
+pub fn erode(height_field: &mut [Vec<f64>], parameters: &ErosionParameters, seed: u64) {
+    let mut field = HeightField::new(height_field);
+    if field.width < 2 || field.length < 2 {
+        return;
+    }
+    erode_hydraulic(&mut field, parameters, seed);
+    erode_thermal(&mut field, parameters);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use game_application::erosion::{erode, DEFAULT_EROSION};
+    use game_application::diamond_square::generate_height_field;
+
+    fn get_bits(height_field: &[Vec<f64>]) -> Vec<u64> {
+        height_field.iter().flat_map(|row| row.iter().map(|height| height.to_bits())).collect()
+    }
+
+    #[test]
+    fn test_same_seed_erodes_identically() {
+        let original = generate_height_field(65, 42);
+        let mut first = original.clone();
+        let mut second = original.clone();
+        erode(&mut first, &DEFAULT_EROSION, 7);
+        erode(&mut second, &DEFAULT_EROSION, 7);
+        assert!(get_bits(&first) != get_bits(&original));
+        assert!(get_bits(&first) == get_bits(&second));
+    }
+
+    #[test]
+    fn test_validate_rejects_counts_above_u32() {
+        let mut parameters = DEFAULT_EROSION;
+        parameters.droplets = u32::MAX as usize + 1;
+        assert!(parameters.validate().is_err());
+        parameters.droplets = u32::MAX as usize;
+        assert!(parameters.validate().is_ok());
+    }
+}
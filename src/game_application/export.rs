@@ -0,0 +1,260 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use game_application::vertex::Vertex;
+
+
+pub enum ExportFormat {
+    Obj,
+    Ply,
+    Gltf
+}
+
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Option<ExportFormat> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("obj") => Some(ExportFormat::Obj),
+            Some("ply") => Some(ExportFormat::Ply),
+            Some("gltf") => Some(ExportFormat::Gltf),
+            _ => None
+        }
+    }
+}
+
+
+// Позиции в Vertex уже в мировых осях, а нормали остались в осях вокселей и смотрят внутрь материала.
+// Перестановка осей заодно делает обход треугольников правильным для внешней стороны
+fn get_normal(vertex: &Vertex) -> [f32; 3] {
+    [-vertex.normal[0], -vertex.normal[2], -vertex.normal[1]]
+}
+
+
+fn write_obj<W: Write>(writer: &mut W, vertices: &[Vertex], indices: &[u32]) -> io::Result<()> {
+    writeln!(writer, "# voxel_craft terrain")?;
+    for vertex in vertices {
+        writeln!(writer, "v {} {} {}", vertex.position[0], vertex.position[1], vertex.position[2])?;
+    }
+    for vertex in vertices {
+        writeln!(writer, "vt {} {}", vertex.tex_coords[0], vertex.tex_coords[1])?;
+    }
+    for vertex in vertices {
+        let normal = get_normal(vertex);
+        writeln!(writer, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+    }
+    // Номера в OBJ начинаются с единицы, у вершины совпадают номера позиции, текстурных координат и нормали
+    for triangle in indices.chunks(3) {
+        let (a, b, c) = (triangle[0] + 1, triangle[1] + 1, triangle[2] + 1);
+        writeln!(writer, "f {}/{}/{} {}/{}/{} {}/{}/{}", a, a, a, b, b, b, c, c, c)?;
+    }
+    Ok(())
+}
+
+
+fn write_floats<W: Write>(writer: &mut W, values: &[f32]) -> io::Result<()> {
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+
+fn write_ply<W: Write>(writer: &mut W, vertices: &[Vertex], indices: &[u32]) -> io::Result<()> {
+    write!(writer, "ply\n\
+                    format binary_little_endian 1.0\n\
+                    comment voxel_craft terrain\n\
+                    element vertex {}\n\
+                    property float x\nproperty float y\nproperty float z\n\
+                    property float nx\nproperty float ny\nproperty float nz\n\
+                    property float s\nproperty float t\n\
+                    element face {}\n\
+                    property list uchar uint vertex_indices\n\
+                    end_header\n", vertices.len(), indices.len() / 3)?;
+    for vertex in vertices {
+        write_floats(writer, &vertex.position)?;
+        write_floats(writer, &get_normal(vertex))?;
+        write_floats(writer, &vertex.tex_coords)?;
+    }
+    for triangle in indices.chunks(3) {
+        writer.write_all(&[3])?;
+        for index in triangle {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+
+fn get_bounds(vertices: &[Vertex]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for vertex in vertices {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(vertex.position[axis]);
+            max[axis] = max[axis].max(vertex.position[axis]);
+        }
+    }
+    (min, max)
+}
+
+
+// glTF 2.0: описание сцены в JSON, а сами массивы в отдельном .bin рядом с ним
+fn write_gltf(path: &Path, vertices: &[Vertex], indices: &[u32]) -> io::Result<()> {
+    let binary_path = path.with_extension("bin");
+    let binary_name = binary_path.file_name().and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Wrong output file name"))?;
+
+    let mut binary = BufWriter::new(File::create(&binary_path)?);
+    for vertex in vertices {
+        write_floats(&mut binary, &vertex.position)?;
+    }
+    for vertex in vertices {
+        write_floats(&mut binary, &get_normal(vertex))?;
+    }
+    for vertex in vertices {
+        write_floats(&mut binary, &vertex.tex_coords)?;
+    }
+    for index in indices {
+        binary.write_all(&index.to_le_bytes())?;
+    }
+    binary.flush()?;
+
+    let count = vertices.len();
+    let (positions_length, normals_length, tex_coords_length) = (12 * count, 12 * count, 8 * count);
+    let indices_length = 4 * indices.len();
+    let (min, max) = get_bounds(vertices);
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, r#"{{
+  "asset": {{"version": "2.0", "generator": "voxel_craft"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0]}}],
+  "nodes": [{{"mesh": 0}}],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2}}, "indices": 3}}]}}],
+  "buffers": [{{"uri": "{}", "byteLength": {}}}],
+  "bufferViews": [
+    {{"buffer": 0, "byteOffset": 0, "byteLength": {}, "target": 34962}},
+    {{"buffer": 0, "byteOffset": {}, "byteLength": {}, "target": 34962}},
+    {{"buffer": 0, "byteOffset": {}, "byteLength": {}, "target": 34962}},
+    {{"buffer": 0, "byteOffset": {}, "byteLength": {}, "target": 34963}}
+  ],
+  "accessors": [
+    {{"bufferView": 0, "componentType": 5126, "count": {}, "type": "VEC3", "min": [{}, {}, {}], "max": [{}, {}, {}]}},
+    {{"bufferView": 1, "componentType": 5126, "count": {}, "type": "VEC3"}},
+    {{"bufferView": 2, "componentType": 5126, "count": {}, "type": "VEC2"}},
+    {{"bufferView": 3, "componentType": 5125, "count": {}, "type": "SCALAR"}}
+  ]
+}}
+"#,
+           binary_name, positions_length + normals_length + tex_coords_length + indices_length,
+           positions_length,
+           positions_length, normals_length,
+           positions_length + normals_length, tex_coords_length,
+           positions_length + normals_length + tex_coords_length, indices_length,
+           count, min[0], min[1], min[2], max[0], max[1], max[2],
+           count,
+           count,
+           indices.len())?;
+    writer.flush()
+}
+
+
+pub fn export_mesh(path: &Path, vertices: &[Vertex], indices: &[u32]) -> io::Result<()> {
+    let format = ExportFormat::from_path(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Unknown export format, use .obj, .ply or .gltf"))?;
+    if indices.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Nothing to export"));
+    }
+    match format {
+        ExportFormat::Obj => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            write_obj(&mut writer, vertices, indices)?;
+            writer.flush()
+        },
+        ExportFormat::Ply => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            write_ply(&mut writer, vertices, indices)?;
+            writer.flush()
+        },
+        ExportFormat::Gltf => write_gltf(path, vertices, indices)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    // Квадрат из двух треугольников
+    fn get_square() -> (Vec<Vertex>, Vec<u32>) {
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let vertices = corners.iter().map(|corner| Vertex {
+            position: [corner[0], 0.0, corner[1]],
+            normal: [0.0, 0.0, -1.0],
+            tex_coords: *corner,
+            material: 0
+        }).collect();
+        (vertices, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    // Все числа, записанные в JSON после ключа
+    fn get_numbers(json: &str, key: &str) -> Vec<usize> {
+        let pattern = format!("\"{}\": ", key);
+        json.match_indices(&pattern).map(|(start, _)| {
+            let digits: String = json[start + pattern.len()..].chars().take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse().unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn test_obj_indices_start_at_one() {
+        let (vertices, indices) = get_square();
+        let mut output = Vec::new();
+        write_obj(&mut output, &vertices, &indices).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert_eq!(text.lines().filter(|line| line.starts_with("v ")).count(), 4);
+        assert_eq!(text.lines().filter(|line| line.starts_with("vn ")).count(), 4);
+        let faces: Vec<&str> = text.lines().filter(|line| line.starts_with("f ")).collect();
+        assert_eq!(faces, vec!["f 1/1/1 2/2/2 3/3/3", "f 1/1/1 3/3/3 4/4/4"]);
+    }
+
+    #[test]
+    fn test_ply_header_matches_body() {
+        let (vertices, indices) = get_square();
+        let mut output = Vec::new();
+        write_ply(&mut output, &vertices, &indices).unwrap();
+        let end = b"end_header\n";
+        let header_length = output.windows(end.len()).position(|window| window == end).unwrap() + end.len();
+        let header = String::from_utf8(output[..header_length].to_vec()).unwrap();
+        assert!(header.contains("element vertex 4\n"));
+        assert!(header.contains("element face 2\n"));
+        // Восемь float на вершину, у грани байт количества и три индекса
+        assert_eq!(output.len() - header_length, 4 * 8 * 4 + 2 * (1 + 3 * 4));
+    }
+
+    #[test]
+    fn test_gltf_views_cover_binary() {
+        let (vertices, indices) = get_square();
+        let path = env::temp_dir().join(format!("voxel_craft_export_{}.gltf", process::id()));
+        let binary_path = path.with_extension("bin");
+        export_mesh(&path, &vertices, &indices).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        let binary_length = fs::metadata(&binary_path).unwrap().len() as usize;
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&binary_path).unwrap();
+
+        let lengths = get_numbers(&json, "byteLength");
+        let offsets = get_numbers(&json, "byteOffset");
+        // Первая длина у буфера, остальные у его частей, которые идут подряд без промежутков
+        assert_eq!(lengths[0], binary_length);
+        assert_eq!(offsets.len(), lengths.len() - 1);
+        let mut end = 0;
+        for (offset, length) in offsets.iter().zip(lengths[1..].iter()) {
+            assert_eq!(*offset, end);
+            end += length;
+        }
+        assert_eq!(end, binary_length);
+    }
+}
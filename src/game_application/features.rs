@@ -0,0 +1,458 @@
+use game_application::rand::{Rng, XorShiftRng};
+use game_application::cgmath::Vector3;
+use game_application::random::{get_rng, get_cell_seed};
+use game_application::chunk::{Chunk, ChunkPosition, CHUNK_SIZE, get_chunk_origin};
+use game_application::biomes::{Biome, BiomeMap};
+use game_application::caves::Caves;
+use game_application::material::{BlockId, AIR, STONE, GRAVEL, WOOD, LEAVES};
+
+
+// Участок, у которого свой набор точек-кандидатов
+const CELL_SIZE: i32 = 32;
+// Сколько кандидатов приходится на круг с диаметром spacing. Чем больше, тем плотнее итоговая выборка
+const CANDIDATES_PER_DISK: f64 = 2.0;
+// Насколько далеко по горизонтали от своей точки может дотянуться шаблон
+const MAX_REACH: i32 = 5;
+// Насколько выше поверхности может подняться шаблон
+pub const MAX_FEATURE_HEIGHT: i32 = 12;
+// Руины ставятся только на ровное место
+const MAX_RUIN_SLOPE: i32 = 2;
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct FeatureParameters {
+    // Наименьшее расстояние между объектами в вокселях
+    pub spacing: f64,
+    pub trees: bool,
+    pub boulders: bool,
+    pub ruins: bool
+}
+
+
+pub const DEFAULT_FEATURES: FeatureParameters = FeatureParameters {
+    spacing: 6.0,
+    trees: true,
+    boulders: true,
+    ruins: true
+};
+
+
+pub const NO_FEATURES: FeatureParameters = FeatureParameters {
+    trees: false,
+    boulders: false,
+    ruins: false,
+    .. DEFAULT_FEATURES
+};
+
+
+impl FeatureParameters {
+    fn is_enabled(&self) -> bool {
+        self.trees || self.boulders || self.ruins
+    }
+
+    // Объекты ищутся в соседних клетках размером spacing, поэтому он должен быть конечным
+    pub fn validate(&self) -> Result<(), String> {
+        if self.spacing < 1.0 || !self.spacing.is_finite() {
+            return Err("Feature spacing must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum FeatureKind {
+    BroadleafTree,
+    Conifer,
+    Boulder,
+    Ruin
+}
+
+
+// Воксели объекта относительно вокселя поверхности под его точкой
+struct Template {
+    voxels: Vec<(Vector3<i32>, BlockId)>
+}
+
+
+impl Template {
+    fn new() -> Template {
+        Template {
+            voxels: Vec::new()
+        }
+    }
+
+    fn add(&mut self, x: i32, y: i32, z: i32, block: BlockId) {
+        self.voxels.push((Vector3::new(x, y, z), block));
+    }
+
+    // Ствол высотой height и шар кроны вокруг его верхушки
+    fn build_broadleaf_tree(rng: &mut XorShiftRng) -> Template {
+        let mut template = Template::new();
+        let height = rng.gen_range(4, 7);
+        let radius = rng.gen_range(2, 4);
+        for z in 1..(height + 1) {
+            template.add(0, 0, z, WOOD);
+        }
+        for dz in -1..(radius + 1) {
+            for dy in -radius..(radius + 1) {
+                for dx in -radius..(radius + 1) {
+                    // Край кроны неровный
+                    let distance = dx * dx + dy * dy + dz * dz;
+                    if distance <= radius * radius || (distance <= radius * radius + 2 && rng.gen::<f64>() < 0.5) {
+                        template.add(dx, dy, height + dz, LEAVES);
+                    }
+                }
+            }
+        }
+        template
+    }
+
+    // Ель: высокий ствол и ярусы хвои, сужающиеся к верхушке
+    fn build_conifer(rng: &mut XorShiftRng) -> Template {
+        let mut template = Template::new();
+        let height = rng.gen_range(6, 10);
+        for z in 1..(height + 1) {
+            template.add(0, 0, z, WOOD);
+        }
+        for z in 3..(height + 2) {
+            let radius = ((height + 2 - z) as f64 / 2.5).ceil().min(3.0) as i32;
+            for dy in -radius..(radius + 1) {
+                for dx in -radius..(radius + 1) {
+                    if (dx * dx + dy * dy <= radius * radius && (dx, dy) != (0, 0)) || z > height {
+                        template.add(dx, dy, z, LEAVES);
+                    }
+                }
+            }
+        }
+        template
+    }
+
+    // Приплюснутый эллипсоид, наполовину ушедший в землю
+    fn build_boulder(rng: &mut XorShiftRng) -> Template {
+        let mut template = Template::new();
+        let (radius_x, radius_y, radius_z): (f64, f64, f64) = (rng.gen_range(1.0, 2.5), rng.gen_range(1.0, 2.5), rng.gen_range(1.0, 2.0));
+        let (reach_x, reach_y, reach_z) = (radius_x.ceil() as i32, radius_y.ceil() as i32, radius_z.ceil() as i32);
+        for dz in -reach_z..(reach_z + 1) {
+            for dy in -reach_y..(reach_y + 1) {
+                for dx in -reach_x..(reach_x + 1) {
+                    let distance = (dx as f64 / radius_x).powi(2) + (dy as f64 / radius_y).powi(2) + (dz as f64 / radius_z).powi(2);
+                    if distance <= 1.0 - 0.3 * rng.gen::<f64>() {
+                        template.add(dx, dy, dz, STONE);
+                    }
+                }
+            }
+        }
+        template
+    }
+
+    // Квадрат обвалившихся стен с проемом и щебнем внутри. Стены уходят в землю, чтобы не висеть на склоне
+    fn build_ruin(rng: &mut XorShiftRng) -> Template {
+        let mut template = Template::new();
+        let half = rng.gen_range(2, MAX_REACH);
+        let door = rng.gen_range(-half + 1, half);
+        for dy in -half..(half + 1) {
+            for dx in -half..(half + 1) {
+                let wall = dx.abs() == half || dy.abs() == half;
+                if wall && !(dy == -half && dx == door) {
+                    let height = rng.gen_range(0, 4);
+                    for z in -2..(height + 1) {
+                        template.add(dx, dy, z, STONE);
+                    }
+                    if height == 0 {
+                        template.add(dx, dy, 1, GRAVEL);
+                    }
+                } else if !wall && rng.gen::<f64>() < 0.2 {
+                    template.add(dx, dy, 1, GRAVEL);
+                }
+            }
+        }
+        template
+    }
+}
+
+
+impl FeatureKind {
+    fn build_template(&self, rng: &mut XorShiftRng) -> Template {
+        match *self {
+            FeatureKind::BroadleafTree => Template::build_broadleaf_tree(rng),
+            FeatureKind::Conifer => Template::build_conifer(rng),
+            FeatureKind::Boulder => Template::build_boulder(rng),
+            FeatureKind::Ruin => Template::build_ruin(rng)
+        }
+    }
+}
+
+
+// Вероятности дерева, валуна и руин в точке выборки для каждого биома
+fn get_probabilities(biome: Biome) -> (f64, f64, f64) {
+    match biome {
+        Biome::Plains => (0.5, 0.08, 0.01),
+        Biome::Desert => (0.0, 0.12, 0.02),
+        Biome::Mountains => (0.08, 0.35, 0.0),
+        Biome::Tundra => (0.35, 0.12, 0.005),
+        Biome::Ocean => (0.0, 0.05, 0.0)
+    }
+}
+
+
+#[derive(Copy, Clone)]
+struct Candidate {
+    x: i32,
+    y: i32,
+    // Из двух близких кандидатов остается тот, у кого больше priority. Он же зерно объекта
+    priority: u64
+}
+
+
+impl Candidate {
+    fn get_key(&self) -> (u64, i32, i32) {
+        (self.priority, self.x, self.y)
+    }
+}
+
+
+// Расстановка объектов поверх рельефа выборкой Пуассона: кандидаты разбросаны случайно по участкам,
+// и каждый остается, только если рядом с ним нет кандидата важнее. Решение зависит лишь от кандидатов
+// в радиусе spacing, поэтому любой чанк видит те же объекты, что и его соседи
+pub struct Features {
+    parameters: FeatureParameters,
+    seed: u64
+}
+
+
+impl Features {
+    pub fn new(seed: u64, parameters: &FeatureParameters) -> Features {
+        Features {
+            parameters: *parameters,
+            seed: seed ^ 0x3A9D_E25B
+        }
+    }
+
+    fn get_candidates(&self, cell_x: i32, cell_y: i32) -> Vec<Candidate> {
+        let mut rng = get_rng(get_cell_seed(self.seed, cell_x, cell_y));
+        let disk_area = self.parameters.spacing * self.parameters.spacing;
+        let count = (CANDIDATES_PER_DISK * (CELL_SIZE * CELL_SIZE) as f64 / disk_area).ceil() as usize;
+        (0..count).map(|_| Candidate {
+            x: cell_x * CELL_SIZE + rng.gen_range(0, CELL_SIZE),
+            y: cell_y * CELL_SIZE + rng.gen_range(0, CELL_SIZE),
+            priority: rng.gen()
+        }).collect()
+    }
+
+    // Оставшиеся после прореживания точки в прямоугольнике [min, max), в порядке, не зависящем от прямоугольника
+    fn get_points(&self, min: (i32, i32), max: (i32, i32)) -> Vec<Candidate> {
+        let reach = self.parameters.spacing.ceil() as i32;
+        let mut candidates = Vec::new();
+        for cell_y in (min.1 - reach).div_euclid(CELL_SIZE)..((max.1 + reach).div_euclid(CELL_SIZE) + 1) {
+            for cell_x in (min.0 - reach).div_euclid(CELL_SIZE)..((max.0 + reach).div_euclid(CELL_SIZE) + 1) {
+                candidates.extend(self.get_candidates(cell_x, cell_y));
+            }
+        }
+        let spacing_squared = self.parameters.spacing * self.parameters.spacing;
+        let mut points: Vec<Candidate> = candidates.iter()
+            .filter(|candidate| candidate.x >= min.0 && candidate.y >= min.1 && candidate.x < max.0 && candidate.y < max.1)
+            .filter(|candidate| candidates.iter().all(|other| {
+                let distance_squared = ((other.x - candidate.x).pow(2) + (other.y - candidate.y).pow(2)) as f64;
+                distance_squared >= spacing_squared || other.get_key() <= candidate.get_key()
+            }))
+            .cloned().collect();
+        points.sort_by_key(|point| point.get_key());
+        points
+    }
+
+    fn get_kind(&self, biome: Biome, rng: &mut XorShiftRng) -> Option<FeatureKind> {
+        let (tree, boulder, ruin) = get_probabilities(biome);
+        let roll = rng.gen::<f64>();
+        if roll < tree {
+            if !self.parameters.trees {
+                return None;
+            }
+            Some(if biome == Biome::Tundra || biome == Biome::Mountains {FeatureKind::Conifer} else {FeatureKind::BroadleafTree})
+        } else if roll < tree + boulder {
+            if self.parameters.boulders {Some(FeatureKind::Boulder)} else {None}
+        } else if roll < tree + boulder + ruin {
+            if self.parameters.ruins {Some(FeatureKind::Ruin)} else {None}
+        } else {
+            None
+        }
+    }
+
+    // Записывает в чанк части объектов, которые в него попадают. get_column возвращает высоту столбца и уровень
+    // воды над ним. Объекты занимают только воздух, а под водой и за краем карты не ставятся. Землей считается
+    // верхний твердый воксель столбца с учетом нависаний, и если его вырезала пещера, объект не ставится,
+    // чтобы не висеть над входом в нее
+    pub fn decorate<F>(&self, chunk: &mut Chunk, position: &ChunkPosition, get_column: F, caves: &Caves,
+                       biomes: Option<&BiomeMap>, z_limit: i32)
+        where F: Fn(i32, i32) -> Option<(f64, Option<f64>)> {
+        if !self.parameters.is_enabled() {
+            return;
+        }
+        let origin = get_chunk_origin(position);
+        let size = CHUNK_SIZE as i32;
+        let min = (origin.x - MAX_REACH, origin.y - MAX_REACH);
+        let max = (origin.x + size + MAX_REACH, origin.y + size + MAX_REACH);
+        let get_height = |x, y| get_column(x, y).map(|(height, _)| height);
+        let points: Vec<(Candidate, i32)> = self.get_points(min, max).into_iter().filter_map(|point| {
+            let (height, water_level) = get_column(point.x, point.y)?;
+            let ground_z = caves.get_top_voxel(point.x, point.y, height, z_limit);
+            // Объект целиком ниже или выше чанка
+            if ground_z + MAX_FEATURE_HEIGHT < origin.z || ground_z - 3 >= origin.z + size {
+                return None;
+            }
+            if water_level.is_some_and(|level| level.round() as i32 > ground_z) {
+                return None;
+            }
+            Some((point, ground_z))
+        }).collect();
+        if points.is_empty() {
+            return;
+        }
+        // Углы руин дальше от точки, чем край прямоугольника точек
+        let probe = caves.get_probe((min.0 - MAX_REACH, min.1 - MAX_REACH), (max.0 + MAX_REACH, max.1 + MAX_REACH), &get_height);
+        let get_solid_ground = |x, y| get_height(x, y).and_then(|height| {
+            let z = caves.get_top_voxel(x, y, height, z_limit);
+            if probe.is_carved(x, y, z, height) {None} else {Some(z)}
+        });
+        for (point, ground_z) in points {
+            if get_solid_ground(point.x, point.y).is_none() {
+                continue;
+            }
+            let mut rng = get_rng(point.priority);
+            let biome = biomes.map_or(Biome::Plains, |biomes| biomes.get_biome(point.x, point.y));
+            let kind = match self.get_kind(biome, &mut rng) {
+                Some(kind) => kind,
+                None => continue
+            };
+            if kind == FeatureKind::Ruin && !self.is_flat(point, ground_z, &get_solid_ground) {
+                continue;
+            }
+            for (offset, block) in kind.build_template(&mut rng).voxels {
+                let (x, y, z) = (point.x + offset.x - origin.x, point.y + offset.y - origin.y, ground_z + offset.z - origin.z);
+                if x < 0 || y < 0 || z < 0 || x >= size || y >= size || z >= size || origin.z + z >= z_limit {
+                    continue;
+                }
+                let (x, y, z) = (x as usize, y as usize, z as usize);
+                if chunk.get_voxel(x, y, z) == AIR {
+                    chunk.set_voxel(x, y, z, block);
+                }
+            }
+        }
+    }
+
+    fn is_flat<G: Fn(i32, i32) -> Option<i32>>(&self, point: Candidate, ground_z: i32, get_ground: &G) -> bool {
+        let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+        corners.iter().all(|&(dx, dy)| match get_ground(point.x + dx * (MAX_REACH - 1), point.y + dy * (MAX_REACH - 1)) {
+            Some(corner_z) => (corner_z - ground_z).abs() <= MAX_RUIN_SLOPE,
+            None => false
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use game_application::cgmath::Point3;
+    use game_application::chunk::{Chunk, ChunkPosition, CHUNK_SIZE, get_chunk_position};
+    use game_application::caves::NO_CAVES;
+    use game_application::terrain::{TerrainGenerator, NoiseTerrain, TerrainParameters, DEFAULT_TERRAIN};
+    use game_application::material::{BlockId, AIR, WOOD};
+
+    // Столбцы чанков 3 x 3 по всей высоте рельефа
+    const CHUNKS_NUMBER: i32 = 3;
+
+    fn build_chunks(seed: u64, parameters: &TerrainParameters) -> HashMap<ChunkPosition, Chunk> {
+        let terrain = NoiseTerrain::new(seed, parameters);
+        let mut chunks = HashMap::new();
+        for x in 0..CHUNKS_NUMBER {
+            for y in 0..CHUNKS_NUMBER {
+                for z in 0..(terrain.get_max_chunk_z() + 1) {
+                    let position = Point3::new(x, y, z);
+                    chunks.insert(position, terrain.build_chunk(&position));
+                }
+            }
+        }
+        chunks
+    }
+
+    fn get_voxel(chunks: &HashMap<ChunkPosition, Chunk>, x: i32, y: i32, z: i32) -> BlockId {
+        let (position, local) = get_chunk_position(x, y, z);
+        chunks.get(&position).map_or(AIR, |chunk| chunk.get_voxel(local.x, local.y, local.z))
+    }
+
+    // Нижние воксели стволов, кроме стволов у края области, где соседний столбец может быть не построен
+    fn get_trunk_bases(chunks: &HashMap<ChunkPosition, Chunk>) -> Vec<Point3<i32>> {
+        let size = CHUNKS_NUMBER * CHUNK_SIZE as i32;
+        let mut bases = Vec::new();
+        for (position, chunk) in chunks {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        let voxel = Point3::new(position.x * CHUNK_SIZE as i32 + x as i32, position.y * CHUNK_SIZE as i32 + y as i32,
+                                                position.z * CHUNK_SIZE as i32 + z as i32);
+                        if chunk.get_voxel(x, y, z) == WOOD && get_voxel(chunks, voxel.x, voxel.y, voxel.z - 1) != WOOD
+                            && voxel.x > 0 && voxel.y > 0 && voxel.x < size - 1 && voxel.y < size - 1 {
+                            bases.push(voxel);
+                        }
+                    }
+                }
+            }
+        }
+        bases
+    }
+
+    #[test]
+    fn test_trunks_stand_on_solid_ground() {
+        let mut overhangs = NO_CAVES;
+        overhangs.overhangs = 8.0;
+        // Полости без штрафа за малую глубину вырезают большую часть поверхности
+        let mut caverns = NO_CAVES;
+        caverns.caverns = 1.0;
+        caverns.max_depth = 64.0;
+        for caves in &[overhangs, caverns] {
+            let mut parameters = DEFAULT_TERRAIN;
+            parameters.caves = *caves;
+            let chunks = build_chunks(3, &parameters);
+            let bases = get_trunk_bases(&chunks);
+            assert!(!bases.is_empty());
+            for base in bases {
+                assert!(get_voxel(&chunks, base.x, base.y, base.z - 1).is_solid());
+            }
+        }
+    }
+
+    #[test]
+    fn test_features_continue_across_chunk_borders() {
+        let mut parameters = DEFAULT_TERRAIN;
+        parameters.caves = NO_CAVES;
+        let chunks = build_chunks(5, &parameters);
+        let size = CHUNK_SIZE as i32;
+        let mut border_trunks = 0;
+        for base in get_trunk_bases(&chunks) {
+            // Ствол не короче четырех вокселей, а у его верхушки со всех сторон крона
+            let mut top = base.z;
+            while get_voxel(&chunks, base.x, base.y, top + 1) == WOOD {
+                top += 1;
+            }
+            assert!(top - base.z >= 3);
+            for &(dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                assert!(get_voxel(&chunks, base.x + dx, base.y + dy, top) != AIR);
+            }
+            let on_border = |value: i32| value.rem_euclid(size) == 0 || value.rem_euclid(size) == size - 1;
+            if on_border(base.x) || on_border(base.y) || base.z.div_euclid(size) != top.div_euclid(size) {
+                border_trunks += 1;
+            }
+        }
+        assert!(border_trunks > 0);
+    }
+
+    #[test]
+    fn test_same_seed_decorates_identically() {
+        let first = build_chunks(11, &DEFAULT_TERRAIN);
+        let second = build_chunks(11, &DEFAULT_TERRAIN);
+        for (position, chunk) in &first {
+            assert!(chunk.get_voxels() == second[position].get_voxels());
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use game_application::cgmath::{Matrix, Matrix4, Vector4, Point3, InnerSpace};
+use game_application::vertex::Vertex;
+
+
+// Ограничивающий параллелепипед в мировых координатах, оси как у Vertex::position
+#[derive(Copy, Clone, Debug)]
+pub struct BoundingBox {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>
+}
+
+
+impl BoundingBox {
+    pub fn from_vertices(vertices: &[Vertex]) -> BoundingBox {
+        let mut min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+        for vertex in vertices {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(vertex.position[axis]);
+                max[axis] = max[axis].max(vertex.position[axis]);
+            }
+        }
+        BoundingBox {
+            min,
+            max
+        }
+    }
+}
+
+
+// Шесть плоскостей (a, b, c, d), внутренняя сторона каждой - где a * x + b * y + c * z + d >= 0
+pub struct Frustum {
+    planes: [Vector4<f32>; 6]
+}
+
+
+impl Frustum {
+    // Плоскости извлекаются из строк матрицы perspective * view (метод Гриба-Хартмана)
+    pub fn new(perspective: &[[f32; 4]; 4], view: &[[f32; 4]; 4]) -> Frustum {
+        let matrix = Matrix4::from(*perspective) * Matrix4::from(*view);
+        let rows = [matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3)];
+        let mut planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[3] + rows[2],
+            rows[3] - rows[2]
+        ];
+        for plane in planes.iter_mut() {
+            *plane /= plane.truncate().magnitude();
+        }
+        Frustum {
+            planes
+        }
+    }
+
+    // Параллелепипед невидим, если целиком лежит снаружи хотя бы одной плоскости.
+    // Достаточно проверить его вершину, дальше всех продвинутую вдоль нормали плоскости
+    pub fn intersects(&self, bounding_box: &BoundingBox) -> bool {
+        self.planes.iter().all(|plane| {
+            let x = if plane.x >= 0.0 {bounding_box.max.x} else {bounding_box.min.x};
+            let y = if plane.y >= 0.0 {bounding_box.max.y} else {bounding_box.min.y};
+            let z = if plane.z >= 0.0 {bounding_box.max.z} else {bounding_box.min.z};
+            plane.x * x + plane.y * y + plane.z * z + plane.w >= 0.0
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_application::camera::Camera;
+
+    // Камера в начале координат смотрит вдоль +z, угол обзора 60 градусов, znear = 0.001, zfar = 1024
+    fn get_frustum() -> Frustum {
+        let camera = Camera::new(1.0, Point3::new(0.0, 0.0, 0.0));
+        Frustum::new(&camera.get_perspective(), &camera.get_view())
+    }
+
+    fn get_box(min: (f32, f32, f32), max: (f32, f32, f32)) -> BoundingBox {
+        BoundingBox {
+            min: Point3::new(min.0, min.1, min.2),
+            max: Point3::new(max.0, max.1, max.2)
+        }
+    }
+
+    fn assert_plane(plane: Vector4<f32>, expected: [f32; 4]) {
+        for i in 0..4 {
+            assert!((plane[i] - expected[i]).abs() < 1e-3, "{:?} != {:?}", plane, expected);
+        }
+    }
+
+    #[test]
+    fn test_planes_are_extracted_and_normalized() {
+        let frustum = get_frustum();
+        for plane in frustum.planes.iter() {
+            assert!((plane.truncate().magnitude() - 1.0).abs() < 1e-5);
+        }
+        // Ближняя и дальняя плоскости перпендикулярны направлению взгляда
+        assert_plane(frustum.planes[4], [0.0, 0.0, 1.0, -0.001]);
+        // У дальней плоскости d теряет точность в f32 из-за маленького znear
+        assert_plane(frustum.planes[5].truncate().extend(0.0), [0.0, 0.0, -1.0, 0.0]);
+        assert!((frustum.planes[5].w - 1024.0).abs() < 50.0);
+        // Боковые плоскости наклонены на половину угла обзора
+        let (sin, cos) = (::std::f32::consts::PI / 6.0).sin_cos();
+        assert_plane(frustum.planes[2], [0.0, cos, sin, 0.0]);
+        assert_plane(frustum.planes[3], [0.0, -cos, sin, 0.0]);
+    }
+
+    #[test]
+    fn test_box_inside_is_visible() {
+        let frustum = get_frustum();
+        assert!(frustum.intersects(&get_box((-1.0, -1.0, 5.0), (1.0, 1.0, 7.0))));
+    }
+
+    #[test]
+    fn test_box_behind_camera_is_culled() {
+        let frustum = get_frustum();
+        assert!(!frustum.intersects(&get_box((-1.0, -1.0, -7.0), (1.0, 1.0, -5.0))));
+    }
+
+    #[test]
+    fn test_box_straddling_side_plane_is_visible() {
+        let frustum = get_frustum();
+        // На расстоянии 10 видимая полуширина около 5.77
+        assert!(frustum.intersects(&get_box((4.0, -1.0, 9.0), (8.0, 1.0, 11.0))));
+        assert!(frustum.intersects(&get_box((-8.0, -1.0, 9.0), (-4.0, 1.0, 11.0))));
+        assert!(!frustum.intersects(&get_box((7.0, -1.0, 9.0), (9.0, 1.0, 11.0))));
+    }
+}
@@ -0,0 +1,137 @@
+use game_application::cgmath::{Vector3, Point3};
+use game_application::vertex::Vertex;
+use game_application::chunk::{Neighborhood, CHUNK_SIZE};
+use game_application::material::{BlockId, WATER};
+
+
+struct Quad {
+    axis: usize,
+    direction: i32,
+    // Координаты угла квада в вокселях чанка: [ось грани, ось u, ось v]
+    start: [i32; 3],
+    width: i32,
+    height: i32,
+    block: BlockId
+}
+
+
+impl Quad {
+    fn add_vertices(&self, origin: &[i32; 3], voxel_size: f32, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+        let (u_axis, v_axis) = ((self.axis + 1) % 3, (self.axis + 2) % 3);
+        // Как и у марширующих кубов, нормаль и обход в осях вокселей смотрят внутрь материала
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[self.axis] = -self.direction as f32;
+
+        // Грань вокселя лежит на полвокселя от его центра, в удвоенных координатах это +-1
+        let corners = [(0, 0), (self.width, 0), (self.width, self.height), (0, self.height)];
+        let first_index = vertices.len() as u32;
+        for &(du, dv) in &corners {
+            let mut point = Point3::new(0.0, 0.0, 0.0);
+            point[self.axis] = (2 * (origin[self.axis] + self.start[0]) + self.direction) as f32;
+            point[u_axis] = (2 * (origin[u_axis] + self.start[1] + du) - 1) as f32;
+            point[v_axis] = (2 * (origin[v_axis] + self.start[2] + dv) - 1) as f32;
+            vertices.push(Vertex::new(&point, &normal, &[du as f32, dv as f32], &self.block, voxel_size));
+        }
+        let order = if self.direction > 0 {[0, 2, 1, 0, 3, 2]} else {[0, 1, 2, 0, 2, 3]};
+        for &i in &order {
+            indices.push(first_index + i);
+        }
+    }
+}
+
+
+// has_face решает по вокселю и его соседу в направлении direction, нужна ли между ними грань
+fn get_mask<F: Fn(BlockId, BlockId) -> bool>(neighborhood: &Neighborhood, axis: usize, direction: i32, slice: i32,
+                                             has_face: &F) -> Vec<Option<BlockId>> {
+    let size = CHUNK_SIZE as i32;
+    let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut mask = vec![None; CHUNK_SIZE * CHUNK_SIZE];
+    for v in 0..size {
+        for u in 0..size {
+            let mut position = [0; 3];
+            position[axis] = slice;
+            position[u_axis] = u;
+            position[v_axis] = v;
+            let block = neighborhood.get_voxel(position[0], position[1], position[2]);
+            position[axis] += direction;
+            let neighbor = neighborhood.get_voxel(position[0], position[1], position[2]);
+            if has_face(block, neighbor) {
+                mask[(u + v * size) as usize] = Some(block);
+            }
+        }
+    }
+    mask
+}
+
+
+// Жадно объединяет одинаковые соседние грани слоя в прямоугольники
+fn merge_mask(mask: &mut [Option<BlockId>], axis: usize, direction: i32, slice: i32) -> Vec<Quad> {
+    let size = CHUNK_SIZE;
+    let mut quads = Vec::new();
+    for v in 0..size {
+        let mut u = 0;
+        while u < size {
+            let block = match mask[u + v * size] {
+                Some(block) => block,
+                None => {
+                    u += 1;
+                    continue;
+                }
+            };
+            let mut width = 1;
+            while u + width < size && mask[u + width + v * size] == Some(block) {
+                width += 1;
+            }
+            let mut height = 1;
+            while v + height < size && (u..(u + width)).all(|i| mask[i + (v + height) * size] == Some(block)) {
+                height += 1;
+            }
+            for dv in 0..height {
+                for du in 0..width {
+                    mask[u + du + (v + dv) * size] = None;
+                }
+            }
+            quads.push(Quad {
+                axis,
+                direction,
+                start: [slice, u as i32, v as i32],
+                width: width as i32,
+                height: height as i32,
+                block
+            });
+            u += width;
+        }
+    }
+    quads
+}
+
+
+fn get_faces<F: Fn(BlockId, BlockId) -> bool>(neighborhood: &Neighborhood, voxel_size: f32, has_face: F) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let origin = neighborhood.get_origin();
+    let origin = [origin.x, origin.y, origin.z];
+    for axis in 0..3 {
+        for &direction in &[1, -1] {
+            for slice in 0..(CHUNK_SIZE as i32) {
+                let mut mask = get_mask(neighborhood, axis, direction, slice, &has_face);
+                for quad in merge_mask(&mut mask, axis, direction, slice) {
+                    quad.add_vertices(&origin, voxel_size, &mut vertices, &mut indices);
+                }
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+
+pub fn get_vertices(neighborhood: &Neighborhood, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
+    get_faces(neighborhood, voxel_size, |block, neighbor| block.is_solid() && !neighbor.is_solid())
+}
+
+
+// Поверхность воды рисуется кубиками при любом способе построения меша: она всегда ровная,
+// а грани с твердым материалом скрыты рельефом
+pub fn get_water_vertices(neighborhood: &Neighborhood, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
+    get_faces(neighborhood, voxel_size, |block, neighbor| block == WATER && neighbor != WATER && !neighbor.is_solid())
+}
@@ -0,0 +1,41 @@
+use glium;
+use game_application::glutin;
+use game_application::model::Model;
+use game_application::renderer::Renderer;
+use game_application::camera::{Camera, CameraPose};
+use game_application::draw_params::DrawStatistics;
+use game_application::settings::Settings;
+
+extern crate image;
+
+
+// Рисует один кадр без окна, например через программный OpenGL (OSMesa с llvmpipe), и сохраняет его в PNG
+pub fn render_to_png(directory: &str, settings: &Settings, pose: &CameraPose, size: (u32, u32), path: &str) -> Result<DrawStatistics, String> {
+    let (width, height) = size;
+    let context = glutin::HeadlessRendererBuilder::new(width, height).build()
+        .map_err(|error| format!("Failed to create headless context: {}", error))?;
+    let display = glium::HeadlessRenderer::new(context)
+        .map_err(|error| format!("Unsupported OpenGL: {}", error))?;
+    let mut renderer = Renderer::new(&display, directory)?;
+    let camera = Camera::from_pose(width as f32 / height as f32, pose);
+
+    // Кадр снимается, только когда фоновые потоки построили все чанки вокруг камеры
+    let mut model = Model::new(camera.position, settings).map_err(|error| error.to_string())?;
+    model.wait_until_ready(camera.position, |changes| renderer.apply_changes(&display, changes))?;
+
+    let color = glium::texture::Texture2d::empty_with_format(&display, glium::texture::UncompressedFloatFormat::U8U8U8U8,
+                                                              glium::texture::MipmapsOption::NoMipmap, width, height)
+        .map_err(|error| format!("Failed to create color buffer: {:?}", error))?;
+    let depth = glium::framebuffer::DepthRenderBuffer::new(&display, glium::texture::DepthFormat::I24, width, height)
+        .map_err(|error| format!("Failed to create depth buffer: {:?}", error))?;
+    let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(&display, &color, &depth)
+        .map_err(|error| format!("Failed to create framebuffer: {:?}", error))?;
+    let statistics = renderer.draw(&mut framebuffer, &camera);
+
+    let image: glium::texture::RawImage2d<u8> = color.read();
+    // OpenGL хранит строки снизу вверх, а PNG сверху вниз
+    let row_length = 4 * width as usize;
+    let pixels: Vec<u8> = image.data.chunks(row_length).rev().flat_map(|row| row.iter().cloned()).collect();
+    image::save_buffer(path, &pixels, width, height, image::RGBA(8)).map_err(|error| format!("Failed to save {}: {}", path, error))?;
+    Ok(statistics)
+}
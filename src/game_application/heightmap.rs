@@ -0,0 +1,157 @@
+use game_application::png::{self, HasParameters};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+
+const MAX_SAMPLE: f64 = 65535.0;
+// Карта кубическая, и ее сторона не меньше наибольшей высоты. Выше этого построение длилось бы бесконечно
+const MAX_HEIGHT: f64 = 1024.0;
+
+
+// Отображение яркости пикселя в высоту в вокселях: черный - min_height, белый - max_height
+#[derive(Copy, Clone, Debug)]
+pub struct HeightScale {
+    pub min_height: f64,
+    pub max_height: f64
+}
+
+
+// Высоты карты по алмазу-квадрату лежат в отрезке [0, 257)
+pub const DEFAULT_HEIGHT_SCALE: HeightScale = HeightScale {
+    min_height: 0.0,
+    max_height: 256.0
+};
+
+
+impl HeightScale {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.min_height.is_finite() || !self.max_height.is_finite() {
+            return Err("--min-height and --max-height must be finite".to_string());
+        }
+        if self.max_height <= self.min_height {
+            return Err("--max-height must be greater than --min-height".to_string());
+        }
+        if self.max_height > MAX_HEIGHT {
+            return Err(format!("--max-height must not exceed {}", MAX_HEIGHT));
+        }
+        Ok(())
+    }
+
+    fn get_height(&self, intensity: f64) -> f64 {
+        self.min_height + intensity * (self.max_height - self.min_height)
+    }
+
+    fn get_intensity(&self, height: f64) -> f64 {
+        ((height - self.min_height) / (self.max_height - self.min_height)).clamp(0.0, 1.0)
+    }
+}
+
+
+// Размер кубической карты, в которую помещается поле высот вместе с воздухом над самым высоким столбцом
+pub fn get_map_size(height_field: &[Vec<f64>]) -> usize {
+    let width = height_field.first().map_or(0, |row| row.len());
+    let max_height = height_field.iter().flat_map(|row| row.iter()).fold(0.0f64, |max, &height| max.max(height));
+    height_field.len().max(width).max(max_height.ceil() as usize + 2)
+}
+
+
+// Поле высот [y][x] записывается в 16-битный PNG в оттенках серого, строка изображения - строка поля
+pub fn export_heightmap(path: &Path, height_field: &[Vec<f64>], scale: &HeightScale) -> io::Result<()> {
+    let width = height_field.first().map_or(0, |row| row.len());
+    let mut data = Vec::with_capacity(2 * width * height_field.len());
+    for row in height_field {
+        for &height in row {
+            let sample = (scale.get_intensity(height) * MAX_SAMPLE).round() as u16;
+            // В PNG многобайтовые отсчеты хранятся в порядке от старшего байта
+            data.extend_from_slice(&sample.to_be_bytes());
+        }
+    }
+    let mut encoder = png::Encoder::new(File::create(path)?, width as u32, height_field.len() as u32);
+    encoder.set(png::ColorType::Grayscale).set(png::BitDepth::Sixteen);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok(())
+}
+
+
+// Читает PNG любой глубины: серый берется как есть, у цветного усредняются каналы, прозрачность не учитывается
+pub fn import_heightmap(path: &Path, scale: &HeightScale) -> io::Result<Vec<Vec<f64>>> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    // По умолчанию декодер урезает 16 бит до 8, а для рельефа нужна полная точность
+    decoder.set(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+
+    // Глубину берем из заголовка файла: после EXPAND декодер сообщает 8 бит и для 16-битных изображений
+    let sample_size = if reader.info().bit_depth == png::BitDepth::Sixteen {2} else {1};
+    // Тип цвета тоже уже после EXPAND: палитра раскрывается в RGB, или в RGBA, если у нее есть прозрачность
+    let (channels, color_channels) = match info.color_type {
+        png::ColorType::Grayscale => (1, 1),
+        png::ColorType::GrayscaleAlpha => (2, 1),
+        png::ColorType::RGB | png::ColorType::Indexed => (3, 3),
+        png::ColorType::RGBA => (4, 3)
+    };
+    let get_sample = |offset: usize| if sample_size == 2 {
+        u16::from_be_bytes([data[offset], data[offset + 1]]) as f64 / MAX_SAMPLE
+    } else {
+        data[offset] as f64 / 255.0
+    };
+
+    let pixel_size = channels * sample_size;
+    let mut height_field = Vec::with_capacity(info.height as usize);
+    for y in 0..info.height as usize {
+        let row_offset = y * info.line_size;
+        let mut row = Vec::with_capacity(info.width as usize);
+        for x in 0..info.width as usize {
+            let pixel_offset = row_offset + x * pixel_size;
+            let intensity = (0..color_channels).map(|channel| get_sample(pixel_offset + channel * sample_size)).sum::<f64>()
+                / color_channels as f64;
+            row.push(scale.get_height(intensity));
+        }
+        height_field.push(row);
+    }
+    Ok(height_field)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn test_export_import_keeps_16_bits() {
+        let scale = HeightScale {
+            min_height: 0.0,
+            max_height: 256.0
+        };
+        // Соседние высоты отличаются меньше, чем на шаг 8-битной яркости
+        let height_field: Vec<Vec<f64>> = (0..5).map(|y| (0..7).map(|x| 100.0 + 0.01 * (y * 7 + x) as f64).collect()).collect();
+        let path = env::temp_dir().join(format!("voxel_craft_heightmap_{}.png", process::id()));
+        export_heightmap(&path, &height_field, &scale).unwrap();
+        let imported = import_heightmap(&path, &scale);
+        fs::remove_file(&path).unwrap();
+        let imported = imported.unwrap();
+
+        assert_eq!(imported.len(), height_field.len());
+        let step = (scale.max_height - scale.min_height) / MAX_SAMPLE;
+        for (row, imported_row) in height_field.iter().zip(imported.iter()) {
+            assert_eq!(imported_row.len(), row.len());
+            for (height, imported_height) in row.iter().zip(imported_row.iter()) {
+                assert!((height - imported_height).abs() <= step / 2.0, "{} != {}", height, imported_height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_unbounded_heights() {
+        assert!(DEFAULT_HEIGHT_SCALE.validate().is_ok());
+        for &(min_height, max_height) in &[(0.0, f64::INFINITY), (f64::NAN, 10.0), (10.0, 10.0), (0.0, MAX_HEIGHT + 1.0)] {
+            assert!(HeightScale {min_height, max_height}.validate().is_err());
+        }
+    }
+}
@@ -1,60 +1,786 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+#[cfg(test)]
 use game_application::vertex::Vertex;
-use game_application::marching_cubes::get_vertices;
-use game_application::diamond_square::generate_hight_map;
-use game_application::cgmath::Point3;
+#[cfg(test)]
+use game_application::material::STONE;
+use game_application::draw_params::ChunkChange;
+use game_application::chunk::{Chunk, ChunkPosition, Neighborhood, CHUNK_SIZE, CHUNK_VOLUME, get_chunk_position};
+use game_application::mesher::Mesher;
+use game_application::world_file::{WorldHeader, write_header, read_header, write_chunk, read_chunk,
+                                   write_encoded_chunk, encode_chunk, decode_chunk};
+use game_application::terrain::{TerrainGenerator, NoiseTerrain, TerrainParameters, DEFAULT_TERRAIN};
+use game_application::workers::{WorkerPool, Job, JobResult};
+use game_application::transvoxel::{Lod, FULL_DETAIL, MAX_LOD_LEVEL, get_face_bit};
+use game_application::material::{BlockId, AIR, MATERIALS_NUMBER};
+use game_application::cgmath::{Point3, Vector3, InnerSpace};
+
+
+pub const DEFAULT_VIEW_DISTANCE: i32 = 8;
+// Расстояния в чанках, начиная с которых детализация падает на очередной уровень.
+// Соседние чанки отличаются по расстоянию не больше чем на 1, поэтому и по уровню тоже
+const LOD_DISTANCES: [i32; MAX_LOD_LEVEL] = [2, 4, 8];
+
+
+// Воксель, в который попал луч, в координатах вокселей, и нормаль грани, через которую луч в него вошел,
+// в тех же осях: y и z в ней переставлены относительно мировых. Зато voxel + normal - соседний воксель
+// перед этой гранью. distance - расстояние в мировых координатах от начала луча до входа в воксель
+#[derive(Copy, Clone, Debug)]
+pub struct RaycastHit {
+    pub voxel: Point3<i32>,
+    pub normal: Vector3<i32>,
+    pub distance: f32,
+    pub material: BlockId
+}
 
 
 pub struct Map {
     voxel_size: f32,
     map_size: usize,
-    voxels: Vec<Vec<Vec<bool>>>,
+    seed: u64,
+    chunks: HashMap<ChunkPosition, Chunk>,
+    mesher: Mesher,
     camera_position: Point3<f32>,
-    vertices: Vec<Vertex>
+    // Чанки, меш которых устарел и должен быть заново отправлен в видеопамять
+    dirty_chunks: HashSet<ChunkPosition>,
+    // Чанки, измененные игроком: их меши строятся сразу, чтобы правка была видна в том же кадре
+    edited_chunks: HashSet<ChunkPosition>,
+    // Бесконечная карта подгружается столбцами чанков вокруг камеры
+    terrain: Option<Arc<NoiseTerrain>>,
+    terrain_parameters: TerrainParameters,
+    view_distance: i32,
+    loaded_columns: HashSet<(i32, i32)>,
+    // Измененные чанки не перегенерируются, а сохраняются в сжатом виде при выгрузке
+    modified_chunks: HashSet<ChunkPosition>,
+    stored_chunks: HashMap<ChunkPosition, Vec<u8>>,
+    // Потоки создаются при первом запросе изменений, синхронным методам они не нужны
+    workers: Option<WorkerPool>,
+    generating_chunks: HashSet<ChunkPosition>,
+    // Номер последней отправленной задачи построения меша для каждого чанка
+    mesh_versions: HashMap<ChunkPosition, u64>,
+    mesh_version: u64,
+    // Уровень детализации, с которым чанк был отправлен на построение меша
+    chunk_lods: HashMap<ChunkPosition, Lod>,
+    camera_chunk: ChunkPosition,
+    lod_enabled: bool
 }
 
 
 impl Map {
     pub fn new(voxel_size: f32, map_size: usize, camera_position: Point3<f32>) -> Map {
-        let mut voxels = Vec::new();
-        for x in 0..map_size {
-            voxels.push(Vec::new());
-            for y in 0..map_size {
-                voxels[x].push(Vec::new());
-                for _ in 0..map_size {
-                    voxels[x][y].push(false);
+        Map {
+            voxel_size,
+            map_size,
+            seed: 0,
+            chunks: HashMap::new(),
+            mesher: Mesher::MarchingCubes,
+            camera_position,
+            dirty_chunks: HashSet::new(),
+            edited_chunks: HashSet::new(),
+            terrain: None,
+            terrain_parameters: DEFAULT_TERRAIN,
+            view_distance: DEFAULT_VIEW_DISTANCE,
+            loaded_columns: HashSet::new(),
+            modified_chunks: HashSet::new(),
+            stored_chunks: HashMap::new(),
+            workers: None,
+            generating_chunks: HashSet::new(),
+            mesh_versions: HashMap::new(),
+            mesh_version: 0,
+            chunk_lods: HashMap::new(),
+            camera_chunk: Point3::new(0, 0, 0),
+            lod_enabled: true
+        }
+    }
+
+    pub fn new_infinite(voxel_size: f32, seed: u64, parameters: &TerrainParameters, camera_position: Point3<f32>) -> Map {
+        let mut map = Map::new(voxel_size, 0, camera_position);
+        map.seed = seed;
+        map.set_terrain_parameters(parameters);
+        map
+    }
+
+    // Параметры рельефа сохраняются в файле мира, чтобы загруженный мир продолжался тем же рельефом.
+    // Бесконечная карта строит по ним новые чанки, конечная уже построена
+    pub fn set_terrain_parameters(&mut self, parameters: &TerrainParameters) {
+        self.terrain_parameters = *parameters;
+        if self.map_size == 0 {
+            self.terrain = Some(Arc::new(NoiseTerrain::new(self.seed, parameters)));
+        }
+    }
+
+    pub fn set_view_distance(&mut self, view_distance: i32) {
+        self.view_distance = view_distance;
+    }
+
+    pub fn set_lod_enabled(&mut self, lod_enabled: bool) {
+        self.lod_enabled = lod_enabled;
+    }
+
+    pub fn get_voxel_size(&self) -> f32 {
+        self.voxel_size
+    }
+
+    // Чанк вокселя уже построен или точно пуст. До этого get_voxel возвращает воздух
+    pub fn is_voxel_ready(&self, x: i32, y: i32, z: i32) -> bool {
+        let (chunk_position, _) = get_chunk_position(x, y, z);
+        if self.generating_chunks.contains(&chunk_position) {
+            return false;
+        }
+        self.terrain.is_none() || self.loaded_columns.contains(&(chunk_position.x, chunk_position.y))
+    }
+
+    pub fn get_voxel(&self, x: i32, y: i32, z: i32) -> BlockId {
+        let (chunk_position, local) = get_chunk_position(x, y, z);
+        match self.chunks.get(&chunk_position) {
+            Some(chunk) => chunk.get_voxel(local.x, local.y, local.z),
+            None => AIR
+        }
+    }
+
+    // Правка чанка, который еще строится или не загружен, пропала бы при его появлении
+    pub fn set_voxel(&mut self, x: i32, y: i32, z: i32, value: BlockId) {
+        let (chunk_position, local) = get_chunk_position(x, y, z);
+        if !self.is_voxel_ready(x, y, z) || value == AIR && !self.chunks.contains_key(&chunk_position) {
+            return;
+        }
+        self.chunks.entry(chunk_position).or_insert_with(Chunk::new).set_voxel(local.x, local.y, local.z, value);
+        self.modified_chunks.insert(chunk_position);
+        self.mark_dirty(&chunk_position, &local);
+    }
+
+    // Обход вокселей вдоль луча по Amanatides и Woo: луч переходит в соседний воксель через ту грань,
+    // до которой ему ближе всего. Начало луча и расстояния в мировых координатах, как у вершин мешей.
+    // Вода пропускается. Если луч начинается внутри твердого вокселя, нормаль и расстояние нулевые
+    pub fn raycast(&self, origin: Point3<f32>, direction: Vector3<f32>, max_distance: f32) -> Option<RaycastHit> {
+        let length = direction.magnitude();
+        // Бесконечная дальность не остановила бы обход, и луч в пустоту шел бы вечно
+        if length == 0.0 || !length.is_finite() || !max_distance.is_finite() {
+            return None;
+        }
+        // В осях вокселей y и z меняются местами, а грани вокселя лежат в целых точках
+        let start = [origin.x / self.voxel_size + 0.5, origin.z / self.voxel_size + 0.5, origin.y / self.voxel_size + 0.5];
+        let direction = [direction.x / length, direction.z / length, direction.y / length];
+        let mut voxel = Point3::new(start[0].floor() as i32, start[1].floor() as i32, start[2].floor() as i32);
+        let mut steps = [0; 3];
+        // Расстояние вдоль луча до следующей грани по каждой оси и между соседними гранями
+        let mut next_distances = [f32::INFINITY; 3];
+        let mut deltas = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            if direction[axis] > 0.0 {
+                steps[axis] = 1;
+                next_distances[axis] = (voxel[axis] as f32 + 1.0 - start[axis]) / direction[axis];
+                deltas[axis] = 1.0 / direction[axis];
+            } else if direction[axis] < 0.0 {
+                steps[axis] = -1;
+                next_distances[axis] = (start[axis] - voxel[axis] as f32) / -direction[axis];
+                deltas[axis] = -1.0 / direction[axis];
+            }
+        }
+        let max_distance = max_distance / self.voxel_size;
+        let mut normal = Vector3::new(0, 0, 0);
+        let mut distance = 0.0;
+        loop {
+            let material = self.get_voxel(voxel.x, voxel.y, voxel.z);
+            if material.is_solid() {
+                return Some(RaycastHit {
+                    voxel,
+                    normal,
+                    distance: distance * self.voxel_size,
+                    material
+                });
+            }
+            let axis = if next_distances[0] < next_distances[1] {
+                if next_distances[0] < next_distances[2] {0} else {2}
+            } else if next_distances[1] < next_distances[2] {1} else {2};
+            if next_distances[axis] > max_distance {
+                return None;
+            }
+            distance = next_distances[axis];
+            next_distances[axis] += deltas[axis];
+            voxel[axis] += steps[axis];
+            normal = Vector3::new(0, 0, 0);
+            normal[axis] = -steps[axis];
+        }
+    }
+
+    // Воксель на границе чанка попадает в окрестность соседнего чанка, поэтому тот тоже перестраивается
+    fn mark_dirty(&mut self, chunk_position: &ChunkPosition, local: &Point3<usize>) {
+        let get_offsets = |coordinate: usize| -> Vec<i32> {
+            if coordinate == 0 {
+                vec![-1, 0]
+            } else if coordinate == CHUNK_SIZE - 1 {
+                vec![0, 1]
+            } else {
+                vec![0]
+            }
+        };
+        for dx in get_offsets(local.x) {
+            for dy in get_offsets(local.y) {
+                for dz in get_offsets(local.z) {
+                    self.edited_chunks.insert(Point3::new(chunk_position.x + dx, chunk_position.y + dy, chunk_position.z + dz));
                 }
             }
         }
-        Map {
-            voxel_size: voxel_size,
-            map_size: map_size,
-            voxels: voxels,
-            camera_position: camera_position,
-            vertices: Vec::new()
+    }
+
+    pub fn set_mesher(&mut self, mesher: Mesher) {
+        self.mesher = mesher;
+        self.dirty_chunks = self.get_chunks_to_mesh();
+    }
+
+    fn get_chunk_positions(&self) -> Vec<ChunkPosition> {
+        let chunks_number = self.map_size.div_ceil(CHUNK_SIZE) as i32;
+        let mut positions = Vec::new();
+        for x in 0..chunks_number {
+            for y in 0..chunks_number {
+                for z in 0..chunks_number {
+                    positions.push(Point3::new(x, y, z));
+                }
+            }
+        }
+        positions
+    }
+
+    pub fn build_terrain(&mut self, seed: u64, generator: &dyn TerrainGenerator) {
+        self.seed = seed;
+        for position in self.get_chunk_positions() {
+            let chunk = generator.build_chunk(&position);
+            if !chunk.is_empty() {
+                self.chunks.insert(position, chunk);
+            }
+        }
+        self.dirty_chunks = self.get_chunks_to_mesh();
+    }
+
+    // То же, что build_terrain, но чанки строятся в фоновых потоках и появляются по мере готовности
+    pub fn build_terrain_in_background(&mut self, seed: u64, generator: Arc<dyn TerrainGenerator>) {
+        self.seed = seed;
+        for position in self.get_chunk_positions() {
+            self.request_chunk(position, generator.clone());
+        }
+    }
+
+    fn get_workers(&mut self) -> &WorkerPool {
+        self.workers.get_or_insert_with(WorkerPool::new)
+    }
+
+    fn request_chunk(&mut self, position: ChunkPosition, generator: Arc<dyn TerrainGenerator>) {
+        self.generating_chunks.insert(position);
+        self.get_workers().submit(Job::Generate(position, generator));
+    }
+
+    fn insert_chunk(&mut self, position: ChunkPosition, chunk: Chunk) {
+        if chunk.is_empty() {
+            return;
+        }
+        self.chunks.insert(position, chunk);
+        // Новый чанк меняет окрестности всех соседей, но на бесконечной карте трогать имеет смысл
+        // только загруженные столбцы
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let column = (position.x + dx, position.y + dy);
+                if self.terrain.is_some() && !self.loaded_columns.contains(&column) {
+                    continue;
+                }
+                for dz in -1..2 {
+                    self.dirty_chunks.insert(Point3::new(column.0, column.1, position.z + dz));
+                }
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        // Бесконечная карта восстанавливается по зерну, поэтому сохраняются только измененные чанки
+        let chunks: Vec<(&ChunkPosition, &Chunk)> = self.chunks.iter()
+            .filter(|&(position, _)| self.terrain.is_none() || self.modified_chunks.contains(position))
+            .collect();
+        write_header(&mut writer, &WorldHeader {
+            seed: self.seed,
+            voxel_size: self.voxel_size,
+            map_size: self.map_size,
+            terrain: self.terrain_parameters,
+            chunks_number: chunks.len() + self.stored_chunks.len()
+        })?;
+        for (position, chunk) in chunks {
+            write_chunk(&mut writer, position, chunk)?;
+        }
+        for (position, data) in &self.stored_chunks {
+            write_encoded_chunk(&mut writer, position, data)?;
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Map> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header = read_header(&mut reader)?;
+        if header.map_size == 0 {
+            // Чанки бесконечной карты подгрузятся, когда к ним приблизится камера
+            let mut map = Map::new_infinite(header.voxel_size, header.seed, &header.terrain, Point3::new(0.0, 0.0, 0.0));
+            for _ in 0..header.chunks_number {
+                let (position, chunk) = read_chunk(&mut reader)?;
+                map.stored_chunks.insert(position, encode_chunk(&chunk));
+            }
+            return Ok(map);
+        }
+        let mut map = Map::new(header.voxel_size, header.map_size, Point3::new(0.0, 0.0, 0.0));
+        map.seed = header.seed;
+        map.set_terrain_parameters(&header.terrain);
+        for _ in 0..header.chunks_number {
+            let (position, chunk) = read_chunk(&mut reader)?;
+            map.chunks.insert(position, chunk);
+        }
+        map.dirty_chunks = map.get_chunks_to_mesh();
+        Ok(map)
+    }
+
+    // Чанки, которые уже построены: вся конечная карта или загруженные столбцы бесконечной
+    fn get_generated_positions(&self) -> Vec<ChunkPosition> {
+        let max_z = match self.terrain {
+            Some(ref terrain) => terrain.get_max_chunk_z(),
+            None => return self.get_chunk_positions()
+        };
+        self.loaded_columns.iter().flat_map(|column| (0..(max_z + 1)).map(move |z| Point3::new(column.0, column.1, z))).collect()
+    }
+
+    // Число вокселей каждого материала в построенной части мира, по номерам BlockId. Пустые чанки
+    // не хранятся, поэтому их воздух досчитывается по объему
+    pub fn get_material_counts(&self) -> [usize; MATERIALS_NUMBER] {
+        let mut counts = [0; MATERIALS_NUMBER];
+        for chunk in self.chunks.values() {
+            for voxel in chunk.get_voxels() {
+                counts[voxel.0 as usize] += 1;
+            }
+        }
+        let empty_chunks = self.get_generated_positions().iter().filter(|position| !self.chunks.contains_key(position)).count();
+        counts[AIR.0 as usize] += empty_chunks * CHUNK_VOLUME;
+        counts
+    }
+
+    pub fn get_neighborhood(&self, position: &ChunkPosition) -> Neighborhood {
+        let mut neighborhood = Neighborhood::new(*position);
+        for dx in -1..2 {
+            for dy in -1..2 {
+                for dz in -1..2 {
+                    let neighbor_position = Point3::new(position.x + dx, position.y + dy, position.z + dz);
+                    if let Some(chunk) = self.chunks.get(&neighbor_position) {
+                        neighborhood.copy_from(chunk, (dx, dy, dz));
+                    }
+                }
+            }
+        }
+        neighborhood
+    }
+
+    fn get_chunks_to_mesh(&self) -> HashSet<ChunkPosition> {
+        // Кубы на нижней границе чанка лежат в соседнем чанке, поэтому его тоже нужно обработать,
+        // даже если он пустой
+        let mut positions = HashSet::new();
+        for position in self.chunks.keys() {
+            for dx in -1..1 {
+                for dy in -1..1 {
+                    for dz in -1..1 {
+                        positions.insert(Point3::new(position.x + dx, position.y + dy, position.z + dz));
+                    }
+                }
+            }
+        }
+        positions
+    }
+
+    fn get_column(&self, camera_position: Point3<f32>) -> (i32, i32) {
+        // Мировые координаты y и z переставлены относительно вокселей
+        let chunk_length = CHUNK_SIZE as f32 * self.voxel_size;
+        ((camera_position.x / chunk_length).floor() as i32, (camera_position.z / chunk_length).floor() as i32)
+    }
+
+    fn load_column(&mut self, column: (i32, i32)) {
+        let max_z = match self.terrain {
+            Some(ref terrain) => terrain.get_max_chunk_z(),
+            None => return
+        };
+        self.loaded_columns.insert(column);
+        for z in 0..(max_z + 1) {
+            let position = Point3::new(column.0, column.1, z);
+            match self.stored_chunks.remove(&position) {
+                Some(data) => {
+                    self.modified_chunks.insert(position);
+                    let chunk = decode_chunk(&mut &data[..]).expect("Failed to decode stored chunk!");
+                    self.insert_chunk(position, chunk);
+                },
+                None => {
+                    let terrain = self.terrain.clone().unwrap();
+                    self.request_chunk(position, terrain);
+                }
+            }
+        }
+    }
+
+    fn unload_column(&mut self, column: (i32, i32), changes: &mut Vec<ChunkChange>) {
+        let max_z = match self.terrain {
+            Some(ref terrain) => terrain.get_max_chunk_z(),
+            None => return
+        };
+        for z in -1..(max_z + 2) {
+            let position = Point3::new(column.0, column.1, z);
+            if let Some(chunk) = self.chunks.remove(&position) {
+                if self.modified_chunks.remove(&position) {
+                    self.stored_chunks.insert(position, encode_chunk(&chunk));
+                }
+            }
+            self.dirty_chunks.remove(&position);
+            self.edited_chunks.remove(&position);
+            self.generating_chunks.remove(&position);
+            self.mesh_versions.remove(&position);
+            self.chunk_lods.remove(&position);
+            changes.push(ChunkChange::Removed(position));
         }
+        self.loaded_columns.remove(&column);
+        self.get_workers().cancel(|position| (position.x, position.y) == column);
     }
 
-    pub fn build_voxels(&mut self) {
-        let high_map = generate_hight_map(self.map_size);
-        for (y, vector) in high_map.iter().enumerate() {
-            for (x, height) in vector.iter().enumerate() {
-                for z in 0..(*height + 1) {
-                    self.voxels[x][y][z] = true;
+    // Подгружает ближайшие к камере столбцы чанков и выгружает те, что вышли за радиус видимости
+    fn update_streaming(&mut self, camera_position: Point3<f32>) -> Vec<ChunkChange> {
+        let mut changes = Vec::new();
+        if self.terrain.is_none() {
+            return changes;
+        }
+        let center = self.get_column(camera_position);
+        let get_distance = |column: &(i32, i32)| (column.0 - center.0).pow(2) + (column.1 - center.1).pow(2);
+        // Запас в один чанк не дает столбцам на границе постоянно выгружаться и загружаться
+        let unload_distance = (self.view_distance + 1).pow(2);
+        let far_columns: Vec<(i32, i32)> = self.loaded_columns.iter().filter(|column| get_distance(column) > unload_distance).cloned().collect();
+        for column in far_columns {
+            self.unload_column(column, &mut changes);
+        }
+
+        let mut missing_columns = Vec::new();
+        for x in (center.0 - self.view_distance)..(center.0 + self.view_distance + 1) {
+            for y in (center.1 - self.view_distance)..(center.1 + self.view_distance + 1) {
+                if get_distance(&(x, y)) <= self.view_distance.pow(2) && !self.loaded_columns.contains(&(x, y)) {
+                    missing_columns.push((x, y));
                 }
             }
         }
+        // Порядок выполнения задает очередь потоков, поэтому все недостающие столбцы запрашиваются сразу
+        for column in missing_columns {
+            self.load_column(column);
+        }
+        changes
     }
 
-    fn need_to_redraw(&self, camera_position: Point3<f32>) -> bool {
-        self.vertices.len() == 0
+    fn get_camera_chunk(&self, camera_position: Point3<f32>) -> ChunkPosition {
+        let (x, y) = self.get_column(camera_position);
+        let chunk_length = CHUNK_SIZE as f32 * self.voxel_size;
+        Point3::new(x, y, (camera_position.y / chunk_length).floor() as i32)
     }
 
-    pub fn get_vertices(&mut self, camera_position: Point3<f32>) -> Vec<Vertex> {
-        if self.need_to_redraw(camera_position) {
-            self.build_voxels();
-            self.vertices = get_vertices(&self.voxels, self.voxel_size);
-        } 
+    fn get_lod_level(&self, position: &ChunkPosition) -> usize {
+        let distance = (position.x - self.camera_chunk.x).abs()
+            .max((position.y - self.camera_chunk.y).abs())
+            .max((position.z - self.camera_chunk.z).abs());
+        LOD_DISTANCES.iter().filter(|&&lod_distance| distance >= lod_distance).count()
+    }
+
+    fn get_lod(&self, position: &ChunkPosition) -> Lod {
+        if !self.lod_enabled || !self.mesher.supports_lod() {
+            return FULL_DETAIL;
+        }
+        let level = self.get_lod_level(position);
+        let mut transitions = 0;
+        for axis in 0..3 {
+            for side in 0..2 {
+                let mut neighbor = *position;
+                neighbor[axis] += if side == 0 {-1} else {1};
+                if self.get_lod_level(&neighbor) < level {
+                    transitions |= get_face_bit(axis, side);
+                }
+            }
+        }
+        Lod {
+            level,
+            transitions
+        }
+    }
+
+    // После перехода камеры в другой чанк перестраиваются чанки, у которых сменилась детализация
+    fn update_lods(&mut self, camera_chunk: ChunkPosition) {
+        if camera_chunk == self.camera_chunk {
+            return;
+        }
+        self.camera_chunk = camera_chunk;
+        let changed: Vec<ChunkPosition> = self.chunk_lods.iter()
+            .filter(|&(position, lod)| self.get_lod(position) != *lod)
+            .map(|(position, _)| *position)
+            .collect();
+        self.dirty_chunks.extend(changed);
+    }
+
+    // Меш, построенный по окрестности с еще не сгенерированным соседом, все равно пришлось бы перестроить
+    fn is_waiting_for_neighbors(&self, position: &ChunkPosition) -> bool {
+        if self.generating_chunks.is_empty() {
+            return false;
+        }
+        for dx in -1..2 {
+            for dy in -1..2 {
+                for dz in -1..2 {
+                    if self.generating_chunks.contains(&Point3::new(position.x + dx, position.y + dy, position.z + dz)) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    fn submit_meshing(&mut self) {
+        let ready_chunks: Vec<ChunkPosition> = self.dirty_chunks.iter()
+            .filter(|position| !self.is_waiting_for_neighbors(position))
+            .cloned().collect();
+        for position in ready_chunks {
+            self.dirty_chunks.remove(&position);
+            self.mesh_version += 1;
+            self.mesh_versions.insert(position, self.mesh_version);
+            let lod = self.get_lod(&position);
+            self.chunk_lods.insert(position, lod);
+            let job = Job::Mesh(self.get_neighborhood(&position), self.mesher, lod, self.voxel_size, self.mesh_version);
+            self.get_workers().submit(job);
+        }
+    }
+
+    fn receive_results(&mut self, changes: &mut Vec<ChunkChange>) {
+        for result in self.get_workers().get_results() {
+            match result {
+                JobResult::Generated(position, chunk) => {
+                    // Чанк мог быть выгружен, пока строился
+                    if self.generating_chunks.remove(&position) {
+                        self.insert_chunk(position, chunk);
+                    }
+                },
+                JobResult::Meshed(position, version, vertices, indices) => {
+                    if self.mesh_versions.get(&position) != Some(&version) {
+                        continue;
+                    }
+                    self.mesh_versions.remove(&position);
+                    if indices.is_empty() {
+                        changes.push(ChunkChange::Removed(position));
+                    } else {
+                        changes.push(ChunkChange::Updated(position, vertices, indices));
+                    }
+                },
+                // Чанк остается без данных или без меша, но больше не ждет задачу, и карта может стать готовой
+                JobResult::Failed(position, None) => {
+                    self.generating_chunks.remove(&position);
+                },
+                JobResult::Failed(position, Some(version)) => {
+                    if self.mesh_versions.get(&position) == Some(&version) {
+                        self.mesh_versions.remove(&position);
+                    }
+                }
+            }
+        }
+    }
+
+    // Меши измененных чанков строятся в этом же потоке. Результаты уже отправленных задач для них
+    // устарели и будут отброшены, потому что номер задачи забывается
+    fn mesh_edited_chunks(&mut self, changes: &mut Vec<ChunkChange>) {
+        let edited_chunks: Vec<ChunkPosition> = self.edited_chunks.drain().collect();
+        for position in edited_chunks {
+            self.dirty_chunks.remove(&position);
+            self.mesh_versions.remove(&position);
+            let lod = self.get_lod(&position);
+            self.chunk_lods.insert(position, lod);
+            let (vertices, indices) = self.mesher.get_lod_vertices(&self.get_neighborhood(&position), lod, self.voxel_size);
+            if indices.is_empty() {
+                changes.push(ChunkChange::Removed(position));
+            } else {
+                changes.push(ChunkChange::Updated(position, vertices, indices));
+            }
+        }
+    }
+
+    // Все запрошенные чанки построены, и их меши уже отданы через get_changes
+    pub fn is_idle(&self) -> bool {
+        self.generating_chunks.is_empty() && self.dirty_chunks.is_empty() && self.edited_chunks.is_empty() && self.mesh_versions.is_empty()
+    }
+
+    // Возвращает только изменившиеся с прошлого вызова меши чанков, сами меши строятся в фоновых потоках
+    pub fn get_changes(&mut self, camera_position: Point3<f32>) -> Vec<ChunkChange> {
         self.camera_position = camera_position;
-        return self.vertices.clone();
+        let camera_chunk = self.get_camera_chunk(camera_position);
+        self.get_workers().set_center(camera_chunk);
+        self.update_lods(camera_chunk);
+        let mut changes = self.update_streaming(camera_position);
+        self.receive_results(&mut changes);
+        self.submit_meshing();
+        self.mesh_edited_chunks(&mut changes);
+        changes
+    }
+
+    // Меш всей карты одним куском, без учета того, что уже было отдано. Нужен только тестам
+    #[cfg(test)]
+    pub fn get_vertices(&self) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for position in self.get_chunks_to_mesh() {
+            let (chunk_vertices, chunk_indices) = self.mesher.get_vertices(&self.get_neighborhood(&position), self.voxel_size);
+            let offset = vertices.len() as u32;
+            vertices.extend(chunk_vertices);
+            indices.extend(chunk_indices.iter().map(|index| index + offset));
+        }
+        (vertices, indices)
+    }
+}
+
+
+// Пол из камня на высоте z = 0 под квадратом от -8 до 7 по x и y, его верхняя грань на z = 1
+#[cfg(test)]
+pub(crate) fn get_floor_map(voxel_size: f32) -> Map {
+    let mut map = Map::new(voxel_size, 16, Point3::new(0.0, 0.0, 0.0));
+    for x in -8..8 {
+        for y in -8..8 {
+            map.set_voxel(x, y, 0, STONE);
+        }
+    }
+    map
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use game_application::cgmath::{Point3, Vector3};
+    use game_application::chunk::{CHUNK_SIZE, CHUNK_VOLUME};
+    use game_application::terrain::{HeightFieldTerrain, DEFAULT_TERRAIN};
+    use game_application::map::{Map, get_floor_map};
+    use game_application::material::{AIR, STONE, SAND, WATER};
+
+    const VOXEL_SIZE: f32 = 0.5;
+
+    // Центр вокселя в мировых координатах, y и z переставлены
+    fn get_world_point(x: f32, y: f32, z: f32) -> Point3<f32> {
+        Point3::new(x * VOXEL_SIZE, z * VOXEL_SIZE, y * VOXEL_SIZE)
+    }
+
+    #[test]
+    fn test_raycast_hits_floor_from_above() {
+        let map = get_floor_map(VOXEL_SIZE);
+        let hit = map.raycast(get_world_point(2.0, 3.0, 5.0), Vector3::new(0.0, -1.0, 0.0), 10.0).unwrap();
+        assert_eq!(hit.voxel, Point3::new(2, 3, 0));
+        assert_eq!(hit.normal, Vector3::new(0, 0, 1));
+        assert_eq!(hit.distance, 4.5 * VOXEL_SIZE);
+        assert_eq!(hit.material, STONE);
+    }
+
+    #[test]
+    fn test_raycast_hits_wall_across_chunks() {
+        let mut map = get_floor_map(VOXEL_SIZE);
+        map.set_voxel(-40, 1, 1, SAND);
+        let hit = map.raycast(get_world_point(0.0, 1.0, 1.0), Vector3::new(-1.0, 0.0, 0.0), 100.0).unwrap();
+        assert_eq!(hit.voxel, Point3::new(-40, 1, 1));
+        assert_eq!(hit.normal, Vector3::new(1, 0, 0));
+        assert_eq!(hit.distance, 39.5 * VOXEL_SIZE);
+        assert_eq!(hit.material, SAND);
+    }
+
+    #[test]
+    fn test_raycast_normal_is_in_voxel_axes() {
+        let mut map = get_floor_map(VOXEL_SIZE);
+        map.set_voxel(2, 5, 1, STONE);
+        // Луч идет вдоль мировой оси z, то есть вдоль оси y вокселей
+        let hit = map.raycast(get_world_point(2.0, 0.0, 1.0), Vector3::new(0.0, 0.0, 1.0), 10.0).unwrap();
+        assert_eq!(hit.voxel, Point3::new(2, 5, 1));
+        assert_eq!(hit.normal, Vector3::new(0, -1, 0));
+        assert_eq!(hit.distance, 4.5 * VOXEL_SIZE);
+        let front = hit.voxel + hit.normal;
+        assert_eq!(map.get_voxel(front.x, front.y, front.z), AIR);
+    }
+
+    #[test]
+    fn test_raycast_enters_through_nearest_face() {
+        let mut map = get_floor_map(VOXEL_SIZE);
+        map.set_voxel(3, 0, 2, STONE);
+        // Луч идет вдоль x и вниз и входит в воксель сбоку, не задевая его верхнюю грань
+        let direction = Vector3::new(1.0, -0.25, 0.0);
+        let hit = map.raycast(get_world_point(0.0, 0.0, 2.5), direction, 10.0).unwrap();
+        assert_eq!(hit.voxel, Point3::new(3, 0, 2));
+        assert_eq!(hit.normal, Vector3::new(-1, 0, 0));
+        // Сверху тот же воксель задевается через верхнюю грань
+        let hit = map.raycast(get_world_point(2.0, 0.0, 4.0), Vector3::new(1.0, -2.0, 0.0), 10.0).unwrap();
+        assert_eq!(hit.voxel, Point3::new(3, 0, 2));
+        assert_eq!(hit.normal, Vector3::new(0, 0, 1));
+    }
+
+    #[test]
+    fn test_raycast_passes_water_and_respects_distance() {
+        let mut map = get_floor_map(VOXEL_SIZE);
+        map.set_voxel(2, 2, 1, WATER);
+        let origin = get_world_point(2.0, 2.0, 4.0);
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        let hit = map.raycast(origin, down, 10.0).unwrap();
+        assert_eq!(hit.voxel, Point3::new(2, 2, 0));
+        // До верхней грани пола 3.5 вокселя
+        assert_eq!(hit.distance, 3.5 * VOXEL_SIZE);
+        assert!(map.raycast(origin, down, 3.0 * VOXEL_SIZE).is_none());
+        assert!(map.raycast(origin, Vector3::new(0.0, 1.0, 0.0), 100.0).is_none());
+        assert!(map.raycast(origin, Vector3::new(0.0, 0.0, 0.0), 100.0).is_none());
+        // Попадание ровно на пределе дальности засчитывается
+        assert_eq!(map.raycast(origin, down, 3.5 * VOXEL_SIZE).unwrap().distance, 3.5 * VOXEL_SIZE);
+    }
+
+    #[test]
+    fn test_raycast_rejects_infinite_distance() {
+        let map = get_floor_map(VOXEL_SIZE);
+        let origin = get_world_point(2.0, 2.0, 4.0);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        assert!(map.raycast(origin, up, f32::INFINITY).is_none());
+        assert!(map.raycast(origin, up, f32::NAN).is_none());
+        assert!(map.raycast(origin, Vector3::new(f32::NAN, 1.0, 0.0), 10.0).is_none());
+    }
+
+    #[test]
+    fn test_raycast_starts_inside_solid() {
+        let map = get_floor_map(VOXEL_SIZE);
+        let hit = map.raycast(get_world_point(1.0, 1.0, 0.2), Vector3::new(0.0, 1.0, 0.0), 10.0).unwrap();
+        assert_eq!(hit.voxel, Point3::new(1, 1, 0));
+        assert_eq!(hit.normal, Vector3::new(0, 0, 0));
+        assert_eq!(hit.distance, 0.0);
+    }
+
+    #[test]
+    fn test_set_voxel_ignores_generating_chunk() {
+        let terrain = HeightFieldTerrain::new(vec![vec![10.0; CHUNK_SIZE]; CHUNK_SIZE], CHUNK_SIZE, 1, &DEFAULT_TERRAIN);
+        let camera_position = Point3::new(0.0, 0.0, 0.0);
+        let mut map = Map::new(0.01, CHUNK_SIZE, camera_position);
+        map.build_terrain_in_background(1, Arc::new(terrain));
+        // Результаты потоков забирает только get_changes, поэтому чанк пока строится
+        map.set_voxel(1, 1, 20, STONE);
+        assert!(map.modified_chunks.is_empty());
+
+        while !map.is_idle() {
+            map.get_changes(camera_position);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(map.get_voxel(1, 1, 20), AIR);
+        map.set_voxel(1, 1, 20, STONE);
+        assert_eq!(map.get_voxel(1, 1, 20), STONE);
+    }
+
+    #[test]
+    fn test_material_counts_cover_empty_chunks() {
+        // Рельеф заполняет только нижний слой чанков, верхние пусты и не хранятся
+        let terrain = HeightFieldTerrain::new(vec![vec![10.0; 2 * CHUNK_SIZE]; 2 * CHUNK_SIZE], 2 * CHUNK_SIZE, 1, &DEFAULT_TERRAIN);
+        let mut map = Map::new(0.01, 2 * CHUNK_SIZE, Point3::new(0.0, 0.0, 0.0));
+        map.build_terrain(1, &terrain);
+        assert!(map.chunks.len() < 8);
+        let counts = map.get_material_counts();
+        assert_eq!(counts.iter().sum::<usize>(), 8 * CHUNK_VOLUME);
+        assert!(counts[AIR.0 as usize] > 4 * CHUNK_VOLUME);
     }
 }
@@ -1,11 +1,11 @@
-use std::collections::HashMap;
-use game_application::cgmath::{Vector3, Point3};
+use game_application::cgmath::Point3;
 use game_application::vertex::Vertex;
-use game_application::geometry::normalize;
 use game_application::mesh::Mesh;
+use game_application::chunk::{Neighborhood, CHUNK_SIZE};
+use game_application::material::AIR;
 
 
-const TRIANGLE_TABLE: [[i32; 16]; 256] = [
+pub const TRIANGLE_TABLE: [[i32; 16]; 256] = [
     [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
     [0, 8, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
     [0, 1, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
@@ -265,7 +265,7 @@ const TRIANGLE_TABLE: [[i32; 16]; 256] = [
 ];
 
 
-const COORDS_ADDITIONS: [(usize, usize, usize); 12] = [
+const COORDS_ADDITIONS: [(i32, i32, i32); 12] = [
     (1, 0, 0),
     (2, 1, 0),
     (1, 2, 0),
@@ -281,45 +281,134 @@ const COORDS_ADDITIONS: [(usize, usize, usize); 12] = [
 ];
 
 
-pub fn process_cube(voxels: &Vec<Vec<Vec<bool>>>,
-                    x: usize, y: usize, z: usize,
+// Смещения углов куба в том же порядке, что и биты cube_index
+pub const CORNERS: [(i32, i32, i32); 8] = [
+    (0, 0, 0),
+    (1, 0, 0),
+    (1, 1, 0),
+    (0, 1, 0),
+    (0, 0, 1),
+    (1, 0, 1),
+    (1, 1, 1),
+    (0, 1, 1)
+];
+
+
+// Номера углов, которые соединяет каждое ребро
+pub const EDGE_CORNERS: [(usize, usize); 12] = [
+    (0, 1), (1, 2), (2, 3), (3, 0),
+    (4, 5), (5, 6), (6, 7), (7, 4),
+    (0, 4), (1, 5), (2, 6), (3, 7)
+];
+
+
+pub fn process_cube(neighborhood: &Neighborhood,
+                    x: i32, y: i32, z: i32,
                     mesh: &mut Mesh) {
     let mut cube_index = 0;
-    if voxels[x][y][z] {cube_index |= 1;}
-    if voxels[x + 1][y][z] {cube_index |= 2;}
-    if voxels[x + 1][y + 1][z] {cube_index |= 4;}
-    if voxels[x][y + 1][z] {cube_index |= 8;}
-    if voxels[x][y][z + 1] {cube_index |= 16;}
-    if voxels[x + 1][y][z + 1] {cube_index |= 32;}
-    if voxels[x + 1][y + 1][z + 1] {cube_index |= 64;}
-    if voxels[x][y + 1][z + 1] {cube_index |= 128;}
-    if cube_index == 0 {
+    let mut blocks = [AIR; 8];
+    for (i, corner) in CORNERS.iter().enumerate() {
+        blocks[i] = neighborhood.get_voxel(x + corner.0, y + corner.1, z + corner.2);
+        if blocks[i].is_solid() {
+            cube_index |= 1 << i;
+        }
+    }
+    if cube_index == 0 || cube_index == 255 {
         return;
     }
+    // Вершины считаются в удвоенных мировых координатах, чтобы середины ребер были целыми
+    let origin = neighborhood.get_origin();
+    let (x, y, z) = (origin.x + x, origin.y + y, origin.z + z);
     let mut index = 0;
     while TRIANGLE_TABLE[cube_index][index] != -1 {
         let mut positions = Vec::new();
+        let mut materials = Vec::new();
         for i in 0..3 {
             let addition_index = TRIANGLE_TABLE[cube_index][index + i] as usize;
             positions.push(Point3::new(2 * x + COORDS_ADDITIONS[addition_index].0,
                                        2 * y + COORDS_ADDITIONS[addition_index].1,
                                        2 * z + COORDS_ADDITIONS[addition_index].2));
+            // Вершина лежит на ребре между твердым и пустым вокселем и берет материал твердого
+            let (start, end) = EDGE_CORNERS[addition_index];
+            materials.push(if blocks[start].is_solid() {blocks[start]} else {blocks[end]});
         }
-        mesh.add_triangle(&positions[0..3]);
+        let coordinates: Vec<Point3<f32>> = positions.iter().map(|point| point.cast::<f32>().unwrap()).collect();
+        mesh.add_triangle(&positions[0..3], &coordinates[0..3], &materials[0..3]);
         index += 3;
     }
 }
 
 
-pub fn get_vertices(voxels: &Vec<Vec<Vec<bool>>>, voxel_size: f32) -> Vec<Vertex> {
+// В отличие от process_cube, вершина ставится не в середину ребра,
+// а в точку, где линейно интерполированная плотность равна нулю
+pub fn process_smooth_cube(neighborhood: &Neighborhood,
+                           x: i32, y: i32, z: i32,
+                           mesh: &mut Mesh) {
+    let mut cube_index = 0;
+    let mut blocks = [AIR; 8];
+    let mut densities = [0.0; 8];
+    for (i, corner) in CORNERS.iter().enumerate() {
+        blocks[i] = neighborhood.get_voxel(x + corner.0, y + corner.1, z + corner.2);
+        densities[i] = neighborhood.get_density(x + corner.0, y + corner.1, z + corner.2);
+        if densities[i] > 0.0 {
+            cube_index |= 1 << i;
+        }
+    }
+    if cube_index == 0 || cube_index == 255 {
+        return;
+    }
+    let origin = neighborhood.get_origin();
+    let (x, y, z) = (origin.x + x, origin.y + y, origin.z + z);
+    let mut index = 0;
+    while TRIANGLE_TABLE[cube_index][index] != -1 {
+        let mut points = Vec::new();
+        let mut positions = Vec::new();
+        let mut materials = Vec::new();
+        for i in 0..3 {
+            let edge_index = TRIANGLE_TABLE[cube_index][index + i] as usize;
+            // Середина ребра остается идентификатором вершины, чтобы соседние кубы ее разделяли
+            points.push(Point3::new(2 * x + COORDS_ADDITIONS[edge_index].0,
+                                    2 * y + COORDS_ADDITIONS[edge_index].1,
+                                    2 * z + COORDS_ADDITIONS[edge_index].2));
+            let (start, end) = EDGE_CORNERS[edge_index];
+            let t = densities[start] / (densities[start] - densities[end]);
+            let (start_corner, end_corner) = (CORNERS[start], CORNERS[end]);
+            positions.push(Point3::new(
+                2.0 * (x + start_corner.0) as f32 + 2.0 * t * (end_corner.0 - start_corner.0) as f32,
+                2.0 * (y + start_corner.1) as f32 + 2.0 * t * (end_corner.1 - start_corner.1) as f32,
+                2.0 * (z + start_corner.2) as f32 + 2.0 * t * (end_corner.2 - start_corner.2) as f32
+            ));
+            materials.push(if densities[start] > 0.0 {blocks[start]} else {blocks[end]});
+        }
+        mesh.add_triangle(&points[0..3], &positions[0..3], &materials[0..3]);
+        index += 3;
+    }
+}
+
+
+pub fn get_vertices(neighborhood: &Neighborhood, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let mut mesh = Mesh::new();
+    let size = CHUNK_SIZE as i32;
+    for x in 0..size {
+        for y in 0..size {
+            for z in 0..size {
+                process_cube(neighborhood, x, y, z, &mut mesh);
+            }
+        }
+    }
+    mesh.get_indexed_vertices(voxel_size)
+}
+
+
+pub fn get_smooth_vertices(neighborhood: &Neighborhood, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
     let mut mesh = Mesh::new();
-    let (x_size, y_size, z_size) = (voxels.len(), voxels[0].len(), voxels[0][0].len());
-    for x in 0..(x_size - 1) {
-        for y in 0..(y_size - 1) {
-            for z in 0..(z_size - 1) {
-                process_cube(&voxels, x, y, z, &mut mesh);
+    let size = CHUNK_SIZE as i32;
+    for x in 0..size {
+        for y in 0..size {
+            for z in 0..size {
+                process_smooth_cube(neighborhood, x, y, z, &mut mesh);
             }
         }
     }
-    mesh.get_vertices(voxel_size)
+    mesh.get_indexed_vertices(voxel_size)
 }
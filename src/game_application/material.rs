@@ -0,0 +1,77 @@
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BlockId(pub u8);
+
+
+pub struct Material {
+    pub name: &'static str,
+    pub solid: bool,
+    pub transparent: bool,
+    pub texture_index: u32
+}
+
+
+pub const AIR: BlockId = BlockId(0);
+pub const GRASS: BlockId = BlockId(1);
+pub const DIRT: BlockId = BlockId(2);
+pub const STONE: BlockId = BlockId(3);
+pub const SAND: BlockId = BlockId(4);
+pub const WATER: BlockId = BlockId(5);
+pub const SNOW: BlockId = BlockId(6);
+pub const GRAVEL: BlockId = BlockId(7);
+pub const WOOD: BlockId = BlockId(8);
+pub const LEAVES: BlockId = BlockId(9);
+pub const BEDROCK: BlockId = BlockId(10);
+pub const COAL: BlockId = BlockId(11);
+pub const IRON: BlockId = BlockId(12);
+pub const GOLD: BlockId = BlockId(13);
+
+pub const MATERIALS_NUMBER: usize = 14;
+
+
+// Порядок материалов должен совпадать с номерами BlockId,
+// а texture_index - с таблицей цветов в шейдере materials.frag
+const MATERIALS: [Material; MATERIALS_NUMBER] = [
+    Material {name: "air", solid: false, transparent: true, texture_index: 0},
+    Material {name: "grass", solid: true, transparent: false, texture_index: 1},
+    Material {name: "dirt", solid: true, transparent: false, texture_index: 2},
+    Material {name: "stone", solid: true, transparent: false, texture_index: 3},
+    Material {name: "sand", solid: true, transparent: false, texture_index: 4},
+    Material {name: "water", solid: false, transparent: true, texture_index: 5},
+    Material {name: "snow", solid: true, transparent: false, texture_index: 6},
+    Material {name: "gravel", solid: true, transparent: false, texture_index: 7},
+    Material {name: "wood", solid: true, transparent: false, texture_index: 8},
+    Material {name: "leaves", solid: true, transparent: false, texture_index: 9},
+    Material {name: "bedrock", solid: true, transparent: false, texture_index: 10},
+    Material {name: "coal", solid: true, transparent: false, texture_index: 11},
+    Material {name: "iron", solid: true, transparent: false, texture_index: 12},
+    Material {name: "gold", solid: true, transparent: false, texture_index: 13}
+];
+
+
+impl BlockId {
+    pub fn from_byte(value: u8) -> Option<BlockId> {
+        if (value as usize) < MATERIALS.len() {
+            Some(BlockId(value))
+        } else {
+            None
+        }
+    }
+
+    pub fn get_material(&self) -> &'static Material {
+        &MATERIALS[self.0 as usize]
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.get_material().solid
+    }
+}
+
+
+// Прозрачность по texture_index, который вершины хранят вместо BlockId
+pub fn get_transparent_textures() -> [bool; MATERIALS_NUMBER] {
+    let mut result = [false; MATERIALS_NUMBER];
+    for material in MATERIALS.iter() {
+        result[material.texture_index as usize] = material.transparent;
+    }
+    result
+}
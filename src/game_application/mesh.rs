@@ -1,11 +1,12 @@
-use game_application::cgmath::{Vector3, Point3};
+use game_application::cgmath::{Vector3, Point3, InnerSpace};
 use game_application::vertex::Vertex;
 use std::collections::{HashMap, HashSet};
 use game_application::geometry::normalize;
+use game_application::material::BlockId;
 use std::iter::FromIterator;
 
 
-type Point = Point3<usize>;
+type Point = Point3<i32>;
 
 
 #[derive(Eq, Hash, Copy, Clone)]
@@ -28,11 +29,15 @@ impl Triangle {
         }
     }
 
-    fn get_normal(&self) -> Vector3<f32> {
-        let position1 = self.points[0].cast::<f32>().unwrap();
-        let position2 = self.points[1].cast::<f32>().unwrap();
-        let position3 = self.points[2].cast::<f32>().unwrap();
+    fn get_normal(&self, positions: &HashMap<Point, Point3<f32>>) -> Vector3<f32> {
+        let position1 = positions[&self.points[0]];
+        let position2 = positions[&self.points[1]];
+        let position3 = positions[&self.points[2]];
         let mut normal = (position2 - position1).cross(position3 - position1);
+        // Вырожденные треугольники бывают у гладкого меша, когда вершина совпадает с углом куба
+        if normal.magnitude2() == 0.0 {
+            return normal;
+        }
         normalize(&mut normal);
         normal
     }
@@ -78,6 +83,8 @@ impl PartialEq for Edge {
 pub struct Mesh {
     edge_neighbors: HashMap<Edge, Vec<Triangle>>,
     point_neighbors: HashMap<Point, Vec<Triangle>>,
+    point_materials: HashMap<Point, BlockId>,
+    positions: HashMap<Point, Point3<f32>>,
     triangles: HashSet<Triangle>
 }
 
@@ -87,6 +94,8 @@ impl Mesh {
         Mesh {
             edge_neighbors: HashMap::new(),
             point_neighbors: HashMap::new(),
+            point_materials: HashMap::new(),
+            positions: HashMap::new(),
             triangles: HashSet::new()
         }
     }
@@ -94,14 +103,22 @@ impl Mesh {
     fn get_point_normal(&self, point: &Point) -> Vector3<f32> {
         let mut normal = Vector3::new(0.0, 0.0, 0.0);
         for triangle in &self.point_neighbors[point] {
-            normal += triangle.get_normal();
+            normal += triangle.get_normal(&self.positions);
+        }
+        if normal.magnitude2() == 0.0 {
+            return Vector3::new(0.0, 0.0, 1.0);
         }
         normalize(&mut normal);
         normal
     }
 
-    pub fn add_triangle(&mut self, points: &[Point]) {
+    // points - идентификаторы вершин, positions - их настоящие координаты в тех же единицах
+    pub fn add_triangle(&mut self, points: &[Point], positions: &[Point3<f32>], materials: &[BlockId]) {
         let new_triangle = Triangle::new(points); // Если точек не 3, треугольник не создастся
+        for ((point, position), material) in points.iter().zip(positions.iter()).zip(materials.iter()) {
+            self.positions.insert(*point, *position);
+            self.point_materials.insert(*point, *material);
+        }
         // Добавляем треугольник соседом всем его вершинам
         for point in points {
             if !self.point_neighbors.contains_key(point) {
@@ -121,18 +138,23 @@ impl Mesh {
         self.triangles.insert(new_triangle);
     }
 
-    pub fn get_vertices(&self, voxel_size: f32) -> Vec<Vertex> {
-        let mut shape = Vec::new();
-        let mut normals = HashMap::new();
-        for (point, triangles) in self.point_neighbors.iter() {
-            normals.insert(point, self.get_point_normal(point));
+    // Каждая точка становится одной вершиной, треугольники ссылаются на нее по индексу
+    pub fn get_indexed_vertices(&self, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let mut shape = Vec::with_capacity(self.point_neighbors.len());
+        let mut point_indices = HashMap::with_capacity(self.point_neighbors.len());
+        for point in self.point_neighbors.keys() {
+            let position = self.positions[point];
+            // Текстура проецируется сверху и повторяется на каждом вокселе
+            let tex_coords = [position.x / 2.0, position.y / 2.0];
+            point_indices.insert(*point, shape.len() as u32);
+            shape.push(Vertex::new(&position, &self.get_point_normal(point), &tex_coords, &self.point_materials[point], voxel_size));
         }
+        let mut indices = Vec::with_capacity(3 * self.triangles.len());
         for triangle in self.triangles.iter() {
-            for (i, point) in triangle.points.iter().enumerate() {
-                let tex_coords = if i % 3 == 0 {[0.0, 0.0]} else if i % 3 == 1 {[0.0, 1.0]} else {[1.0, 1.0]};
-                shape.push(Vertex::new(point, &normals[point], &tex_coords, voxel_size));
+            for point in triangle.points.iter() {
+                indices.push(point_indices[point]);
             }
         }
-        shape
+        (shape, indices)
     }
 }
@@ -0,0 +1,63 @@
+use std::str::FromStr;
+use game_application::vertex::Vertex;
+use game_application::chunk::Neighborhood;
+use game_application::marching_cubes;
+use game_application::greedy_meshing;
+use game_application::transvoxel::{self, Lod};
+
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Mesher {
+    MarchingCubes,
+    SmoothMarchingCubes,
+    Blocky
+}
+
+
+// Добавляет к мешу рельефа отдельные треугольники воды, рендерер отличает их по материалу
+fn add_water((mut vertices, mut indices): (Vec<Vertex>, Vec<u32>), neighborhood: &Neighborhood, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let (water_vertices, water_indices) = greedy_meshing::get_water_vertices(neighborhood, voxel_size);
+    let offset = vertices.len() as u32;
+    vertices.extend(water_vertices);
+    indices.extend(water_indices.iter().map(|index| index + offset));
+    (vertices, indices)
+}
+
+
+impl Mesher {
+    pub fn get_vertices(&self, neighborhood: &Neighborhood, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let mesh = match *self {
+            Mesher::MarchingCubes => marching_cubes::get_vertices(neighborhood, voxel_size),
+            Mesher::SmoothMarchingCubes => marching_cubes::get_smooth_vertices(neighborhood, voxel_size),
+            Mesher::Blocky => greedy_meshing::get_vertices(neighborhood, voxel_size)
+        };
+        add_water(mesh, neighborhood, voxel_size)
+    }
+
+    // Упрощенные уровни детализации есть только у марширующих кубов
+    pub fn supports_lod(&self) -> bool {
+        *self != Mesher::Blocky
+    }
+
+    pub fn get_lod_vertices(&self, neighborhood: &Neighborhood, lod: Lod, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
+        if lod.level == 0 || !self.supports_lod() {
+            return self.get_vertices(neighborhood, voxel_size);
+        }
+        let mesh = transvoxel::get_vertices(neighborhood, lod, *self == Mesher::SmoothMarchingCubes, voxel_size);
+        add_water(mesh, neighborhood, voxel_size)
+    }
+}
+
+
+impl FromStr for Mesher {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Mesher, String> {
+        match name {
+            "marching-cubes" => Ok(Mesher::MarchingCubes),
+            "smooth" => Ok(Mesher::SmoothMarchingCubes),
+            "blocky" => Ok(Mesher::Blocky),
+            _ => Err(format!("Unknown mesher: {}", name))
+        }
+    }
+}
@@ -1,6 +1,8 @@
 extern crate glutin;
 extern crate cgmath;
 extern crate rand;
+extern crate transvoxel_data;
+extern crate png;
 
 mod view;
 use self::view::View;
@@ -11,29 +13,107 @@ use self::model::Model;
 mod draw_params;
 mod vertex;
 mod map;
+mod chunk;
+mod material;
 mod camera;
+mod player;
 mod marching_cubes;
 mod diamond_square;
 mod geometry;
 mod mesh;
+mod random;
+mod world_file;
+mod mesher;
+mod greedy_meshing;
+mod transvoxel;
+mod settings;
+mod noise;
+mod terrain;
+mod caves;
+mod biomes;
+mod erosion;
+mod water;
+mod features;
+mod ores;
+mod workers;
+mod frustum;
+mod renderer;
+mod headless;
+mod export;
+mod heightmap;
+
+pub use self::mesher::Mesher;
+pub use self::settings::Settings;
+pub use self::map::DEFAULT_VIEW_DISTANCE;
+pub use self::camera::CameraPose;
+pub use self::heightmap::DEFAULT_HEIGHT_SCALE;
+pub use self::terrain::{Generator, DEFAULT_TERRAIN};
+pub use self::caves::NO_CAVES;
+pub use self::erosion::DEFAULT_EROSION;
+pub use self::water::NO_WATER;
+pub use self::features::NO_FEATURES;
+pub use self::ores::OreParameters;
 
 use game_application::draw_params::DrawParams;
+use game_application::material::BlockId;
 use game_application::cgmath::Point3;
+use std::time::{Duration, Instant};
+use std::path::Path;
 
 
 pub struct GameApplication<'time> {
-    directory: &'time str
+    directory: &'time str,
+    settings: Settings
 }
 
 
 impl<'time> GameApplication<'time> {
-    pub fn new(directory: &'time str) -> GameApplication<'time> {
+    pub fn new(directory: &'time str, settings: Settings) -> GameApplication<'time> {
         GameApplication{
-            directory: directory
+            directory,
+            settings
+        }
+    }
+
+    pub fn render(&self, pose: &CameraPose, size: (u32, u32), path: &str) -> Result<(), String> {
+        let statistics = headless::render_to_png(self.directory, &self.settings, pose, size, path)?;
+        println!("Draw calls: {}, triangles: {}, culled chunks: {}",
+                 statistics.draw_calls, statistics.triangles, statistics.culled_chunks);
+        Ok(())
+    }
+
+    pub fn export(&self, pose: &CameraPose, path: &str) -> Result<(), String> {
+        let mut model = Model::new(pose.position, &self.settings).map_err(|error| error.to_string())?;
+        let (vertices, indices) = model.wait_for_mesh(pose.position)?;
+        export::export_mesh(Path::new(path), &vertices, &indices).map_err(|error| format!("Failed to export {}: {}", path, error))?;
+        println!("Exported {} vertices, {} triangles", vertices.len(), indices.len() / 3);
+        Ok(())
+    }
+
+    // Печатает, сколько вокселей каждого материала в мире вокруг камеры
+    pub fn report_materials(&self, pose: &CameraPose) -> Result<(), String> {
+        let mut model = Model::new(pose.position, &self.settings).map_err(|error| error.to_string())?;
+        model.wait_for_mesh(pose.position)?;
+        let counts = model.get_material_counts();
+        let total = counts.iter().sum::<usize>().max(1);
+        for (index, count) in counts.iter().enumerate() {
+            println!("{:>8} {:>12} {:>8.3}%", BlockId(index as u8).get_material().name, count, *count as f64 * 100.0 / total as f64);
         }
+        Ok(())
+    }
+
+    pub fn export_heightmap(&self, path: &str) -> Result<(), String> {
+        let (height_field, _) = Model::get_water_height_field(&self.settings).map_err(|error| format!("Failed to load heightmap: {}", error))?;
+        heightmap::export_heightmap(Path::new(path), &height_field, &self.settings.height_scale)
+            .map_err(|error| format!("Failed to export {}: {}", path, error))?;
+        println!("Exported {}x{} heightmap", height_field.first().map_or(0, |row| row.len()), height_field.len());
+        Ok(())
     }
 
     fn handle_close_event(&self, event: &glutin::WindowEvent) -> bool {
+        if let glutin::WindowEvent::Closed = *event {
+            return true;
+        }
         if let glutin::WindowEvent::KeyboardInput{input, ..} = *event {
             if let Some(key) = input.virtual_keycode {
                 if let glutin::VirtualKeyCode::Escape = key {
@@ -49,23 +129,41 @@ impl<'time> GameApplication<'time> {
         model.get_draw_params(view.camera.position)
     }
 
-    pub fn start_loop(&mut self) {
+    pub fn start_loop(&mut self) -> Result<(), String> {
         let camera_position = Point3::new(0.0, 0.0, 0.0);
         let mut events_loop = glutin::EventsLoop::new();
-        let mut view = View::new(&events_loop, self.directory, camera_position);
-        let mut model = Model::new(camera_position);
+        let mut view = View::new(&events_loop, self.directory, camera_position)?;
+        let mut model = Model::new(camera_position, &self.settings).map_err(|error| error.to_string())?;
 
         let mut closed = false;
+        let mut last_report = Instant::now();
+        let mut last_frame = Instant::now();
         while !closed {
             events_loop.poll_events(|event| {
                 if let glutin::Event::WindowEvent{event, ..} = event {
                     view.camera.handle_event(&event);
-                    closed = self.handle_close_event(&event);
+                    model.handle_event(&event, &view.camera);
+                    closed = closed || self.handle_close_event(&event);
                 }
             });
-            view.camera.update();
+            let elapsed = last_frame.elapsed().as_secs_f32();
+            last_frame = Instant::now();
+            model.update(&mut view.camera, elapsed);
             let draw_params = self.get_draw_params(&mut model, &mut view);
-            view.draw(draw_params);
+            let statistics = view.draw(draw_params);
+            if self.settings.show_statistics && last_report.elapsed() >= Duration::from_secs(1) {
+                println!("Draw calls: {}, triangles: {}, culled chunks: {}",
+                         statistics.draw_calls, statistics.triangles, statistics.culled_chunks);
+                last_report = Instant::now();
+            }
+        }
+        // Правки мира не пропадают: файл перезаписывается при выходе. Окно уже закрыто,
+        // и ошибка записи только печатается, падение здесь ничего бы не спасло
+        if let Some(ref path) = self.settings.world_path {
+            if let Err(error) = model.save(path) {
+                eprintln!("Failed to save world file {}: {}", path, error);
+            }
         }
+        Ok(())
     }
 }
@@ -1,33 +1,262 @@
-use game_application::draw_params::{DrawParams, UniformsStruct};
+use game_application::draw_params::{DrawParams, UniformsStruct, ChunkChange};
+use game_application::vertex::Vertex;
 use game_application::map::Map;
+use game_application::material::{BlockId, MATERIALS_NUMBER, AIR, BEDROCK, GRASS, DIRT, STONE, SAND, SNOW, GRAVEL, WOOD, LEAVES};
+use game_application::camera::Camera;
+use game_application::player::Player;
+use game_application::settings::Settings;
 use game_application::glutin;
 use game_application::cgmath::Point3;
+use game_application::diamond_square::generate_height_field;
+use game_application::heightmap::{import_heightmap, get_map_size};
+use game_application::terrain::{TerrainGenerator, Generator, NoiseTerrain, HeightFieldTerrain, BASE_HEIGHT, SEA_DEPTH};
+use game_application::biomes::BiomeMap;
+use game_application::erosion::erode;
+use game_application::water::{add_water, WaterLevels};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+// Сколько консольные команды ждут построения чанков вокруг камеры
+const MAX_WAIT: Duration = Duration::from_secs(300);
+
+
+// Для алмаза-квадрата размер карты должен представляться в виде 2 ^ i + 1
+const DIAMOND_SQUARE_MAP_SIZE: usize = 257;
+// Шуму размер не важен, берется целое число чанков
+const NOISE_MAP_SIZE: usize = 256;
+const DIAMOND_SQUARE_BASE_HEIGHT: f64 = (DIAMOND_SQUARE_MAP_SIZE / 2) as f64;
+// Дальность, на которой можно менять воксели, в мировых координатах
+const MAX_EDIT_DISTANCE: f32 = 0.5;
+// Материалы, которые ставятся клавишами 1-8
+const PLACEABLE_MATERIALS: [BlockId; 8] = [GRASS, DIRT, STONE, SAND, SNOW, GRAVEL, WOOD, LEAVES];
 
 
 pub struct Model {
-    map: Map
+    map: Map,
+    player: Player,
+    selected_material: BlockId
 }
 
 
 impl Model {
-    pub fn new(camera_position: Point3<f32>) -> Model {
-        // Размер карты должен представляться в виде 2 ^ i + 1
-        let mut map = Map::new(0.01, 257, camera_position);
-        map.build_voxels();
-        Model{
-            map: map
+    // Испорченный или недоступный файл мира - ошибка пользователя, и она возвращается вместе с путем
+    pub fn new(camera_position: Point3<f32>, settings: &Settings) -> io::Result<Model> {
+        // Рельеф загруженного мира задают параметры из его файла, флаги генерации на него не влияют
+        let mut map = match settings.world_path {
+            Some(ref path) if Path::new(path).exists() => Map::load(path)
+                .map_err(|error| io::Error::new(error.kind(), format!("Failed to load world file {}: {}", path, error)))?,
+            Some(ref path) => {
+                // Для сохранения карта нужна целиком, поэтому ее приходится построить сразу
+                let map = Model::generate_map(camera_position, settings, false)?;
+                map.save(path).map_err(|error| io::Error::new(error.kind(), format!("Failed to save world file {}: {}", path, error)))?;
+                map
+            },
+            None => Model::generate_map(camera_position, settings, true)?
+        };
+        map.set_mesher(settings.mesher);
+        map.set_view_distance(settings.view_distance);
+        map.set_lod_enabled(settings.lod);
+        Ok(Model{
+            map,
+            player: Player::new(),
+            selected_material: PLACEABLE_MATERIALS[0]
+        })
+    }
+
+    // Поле высот конечной карты: из изображения, если оно задано, иначе выбранным генератором по зерну
+    fn get_height_field(settings: &Settings) -> io::Result<Vec<Vec<f64>>> {
+        let mut height_field = match settings.heightmap {
+            Some(ref path) => import_heightmap(Path::new(path), &settings.height_scale)?,
+            None => match settings.generator {
+                Generator::DiamondSquare => {
+                    let mut height_field = generate_height_field(DIAMOND_SQUARE_MAP_SIZE, settings.seed);
+                    if settings.terrain.biomes {
+                        // Алмаз-квадрат колеблется вокруг середины карты примерно на четверть ее размера
+                        let base = DIAMOND_SQUARE_BASE_HEIGHT;
+                        BiomeMap::new(settings.seed).shape_height_field(&mut height_field, base, base / 2.0);
+                    }
+                    height_field
+                },
+                Generator::Noise => {
+                    let terrain = NoiseTerrain::new(settings.seed, &settings.terrain);
+                    (0..NOISE_MAP_SIZE as i32).map(|y| (0..NOISE_MAP_SIZE as i32).map(|x| terrain.get_height(x, y)).collect()).collect()
+                }
+            }
+        };
+        erode(&mut height_field, &settings.terrain.erosion, settings.seed);
+        Ok(height_field)
+    }
+
+    // Поле высот с прорезанными руслами рек, как оно попадает в мир, и уровни воды над столбцами
+    pub fn get_water_height_field(settings: &Settings) -> io::Result<(Vec<Vec<f64>>, WaterLevels)> {
+        let mut height_field = Model::get_height_field(settings)?;
+        let water_levels = add_water(&mut height_field, &settings.terrain.water, Model::get_sea_level(settings), settings.seed);
+        Ok((height_field, water_levels))
+    }
+
+    // Для загруженного изображения средний уровень рельефа неизвестен, и море появляется, только если задан его уровень
+    fn get_sea_level(settings: &Settings) -> Option<f64> {
+        let default_level = match (&settings.heightmap, settings.generator) {
+            (Some(_), _) => None,
+            (None, Generator::DiamondSquare) => Some(DIAMOND_SQUARE_BASE_HEIGHT - SEA_DEPTH),
+            (None, Generator::Noise) => Some(BASE_HEIGHT - SEA_DEPTH)
+        };
+        settings.terrain.water.get_sea_level(default_level)
+    }
+
+    // Размер конечной карты и генератор ее чанков
+    fn get_terrain(settings: &Settings) -> io::Result<(usize, Arc<dyn TerrainGenerator>)> {
+        // Эрозия, озера и реки обрабатывают всю карту, поэтому шум сначала переводится в поле высот
+        if settings.heightmap.is_none() && settings.generator == Generator::Noise && settings.terrain.is_chunk_local() {
+            return Ok((NOISE_MAP_SIZE, Arc::new(NoiseTerrain::new(settings.seed, &settings.terrain))));
+        }
+        let (height_field, water_levels) = Model::get_water_height_field(settings)
+            .map_err(|error| io::Error::new(error.kind(), format!("Failed to load heightmap: {}", error)))?;
+        let map_size = match (&settings.heightmap, settings.generator) {
+            (Some(_), _) => get_map_size(&height_field),
+            (None, Generator::DiamondSquare) => DIAMOND_SQUARE_MAP_SIZE,
+            (None, Generator::Noise) => NOISE_MAP_SIZE
+        };
+        let mut terrain = HeightFieldTerrain::new(height_field, map_size, settings.seed, &settings.terrain);
+        terrain.set_water_levels(water_levels);
+        Ok((map_size, Arc::new(terrain)))
+    }
+
+    fn generate_map(camera_position: Point3<f32>, settings: &Settings, in_background: bool) -> io::Result<Map> {
+        // Зерно печатается там, где по нему строится карта, чтобы мир можно было воспроизвести.
+        // У загруженного мира рельеф уже есть, и зерно из аргументов к нему не относится
+        println!("Seed: {}", settings.seed);
+        if settings.infinite {
+            return Ok(Map::new_infinite(0.01, settings.seed, &settings.terrain, camera_position));
+        }
+        let (map_size, generator) = Model::get_terrain(settings)?;
+        let mut map = Map::new(0.01, map_size, camera_position);
+        map.set_terrain_parameters(&settings.terrain);
+        if in_background {
+            map.build_terrain_in_background(settings.seed, generator);
+        } else {
+            map.build_terrain(settings.seed, &*generator);
         }
+        Ok(map)
     }
 
     pub fn get_draw_params(&mut self, camera_position: Point3<f32>) -> DrawParams {
-        let shape = self.map.get_vertices(camera_position);
         DrawParams{
-            shape: shape,
+            changes: self.map.get_changes(camera_position),
             uniforms: UniformsStruct{}
         }
     }
 
-    pub fn handle_event(&mut self, event: &glutin::WindowEvent) {
-        // Обрабытывает сигналы для бизнес логики
+    pub fn is_ready(&self) -> bool {
+        self.map.is_idle()
+    }
+
+    pub fn get_material_counts(&self) -> [usize; MATERIALS_NUMBER] {
+        self.map.get_material_counts()
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        self.map.save(path)
+    }
+
+    // Отдает изменения мешей, пока фоновые потоки не построят все чанки вокруг камеры.
+    // Если за MAX_WAIT карта так и не готова, что-то зависло, и ждать дальше бессмысленно
+    pub fn wait_until_ready<F: FnMut(Vec<ChunkChange>)>(&mut self, camera_position: Point3<f32>, mut handle_changes: F) -> Result<(), String> {
+        let start = Instant::now();
+        loop {
+            handle_changes(self.map.get_changes(camera_position));
+            if self.is_ready() {
+                return Ok(());
+            }
+            if start.elapsed() > MAX_WAIT {
+                return Err(format!("Chunks around the camera are not ready after {} seconds", MAX_WAIT.as_secs()));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // Дожидается, пока фоновые потоки построят все чанки вокруг камеры, и склеивает их меши в один
+    pub fn wait_for_mesh(&mut self, camera_position: Point3<f32>) -> Result<(Vec<Vertex>, Vec<u32>), String> {
+        let mut meshes = HashMap::new();
+        self.wait_until_ready(camera_position, |changes| {
+            for change in changes {
+                match change {
+                    ChunkChange::Updated(position, vertices, indices) => {
+                        meshes.insert(position, (vertices, indices));
+                    },
+                    ChunkChange::Removed(position) => {
+                        meshes.remove(&position);
+                    }
+                }
+            }
+        })?;
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for (chunk_vertices, chunk_indices) in meshes.into_values() {
+            let offset = vertices.len() as u32;
+            vertices.extend(chunk_vertices);
+            indices.extend(chunk_indices.iter().map(|index| index + offset));
+        }
+        Ok((vertices, indices))
+    }
+
+    // Камера либо летает свободно, либо следует за игроком. Время кадра в секундах
+    pub fn update(&mut self, camera: &mut Camera, elapsed: f32) {
+        if self.player.is_walking() {
+            camera.rotate(elapsed);
+            self.player.update(&self.map, camera, elapsed);
+        } else {
+            camera.update(elapsed);
+        }
+    }
+
+    // Левая кнопка мыши убирает воксель под прицелом, правая приставляет к нему выбранный материал,
+    // материал выбирается цифрами, а F переключает ходьбу и полет
+    pub fn handle_event(&mut self, event: &glutin::WindowEvent, camera: &Camera) {
+        match *event {
+            glutin::WindowEvent::MouseInput{state: glutin::ElementState::Pressed, button, ..} => {
+                let hit = match self.map.raycast(camera.position, camera.get_direction(), MAX_EDIT_DISTANCE) {
+                    Some(hit) => hit,
+                    None => return
+                };
+                let (x, y, z) = (hit.voxel.x, hit.voxel.y, hit.voxel.z);
+                match button {
+                    // Коренная порода не разрушается, иначе можно провалиться под карту
+                    glutin::MouseButton::Left if hit.material != BEDROCK => self.map.set_voxel(x, y, z, AIR),
+                    // Луч начался внутри вокселя, и грань, к которой приставлять, неизвестна
+                    glutin::MouseButton::Right if hit.distance > 0.0 => {
+                        let target = hit.voxel + hit.normal;
+                        if !self.player.overlaps(&target) {
+                            self.map.set_voxel(target.x, target.y, target.z, self.selected_material);
+                        }
+                    },
+                    _ => ()
+                }
+            },
+            glutin::WindowEvent::KeyboardInput{input, ..} if input.state == glutin::ElementState::Pressed => {
+                let index = match input.virtual_keycode {
+                    Some(glutin::VirtualKeyCode::Key1) => 0,
+                    Some(glutin::VirtualKeyCode::Key2) => 1,
+                    Some(glutin::VirtualKeyCode::Key3) => 2,
+                    Some(glutin::VirtualKeyCode::Key4) => 3,
+                    Some(glutin::VirtualKeyCode::Key5) => 4,
+                    Some(glutin::VirtualKeyCode::Key6) => 5,
+                    Some(glutin::VirtualKeyCode::Key7) => 6,
+                    Some(glutin::VirtualKeyCode::Key8) => 7,
+                    Some(glutin::VirtualKeyCode::F) => {
+                        self.player.toggle_walking(&self.map, camera);
+                        return;
+                    },
+                    _ => return
+                };
+                self.selected_material = PLACEABLE_MATERIALS[index];
+            },
+            _ => ()
+        }
     }
 }
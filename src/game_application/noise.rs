@@ -0,0 +1,278 @@
+use game_application::rand::Rng;
+use game_application::random::get_rng;
+use std::str::FromStr;
+
+
+const GRADIENTS_2D: [(f64, f64); 8] = [
+    (1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0),
+    (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)
+];
+
+
+// Середины ребер куба
+const GRADIENTS_3D: [(f64, f64, f64); 12] = [
+    (1.0, 1.0, 0.0), (-1.0, 1.0, 0.0), (1.0, -1.0, 0.0), (-1.0, -1.0, 0.0),
+    (1.0, 0.0, 1.0), (-1.0, 0.0, 1.0), (1.0, 0.0, -1.0), (-1.0, 0.0, -1.0),
+    (0.0, 1.0, 1.0), (0.0, -1.0, 1.0), (0.0, 1.0, -1.0), (0.0, -1.0, -1.0)
+];
+
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+
+// Перестановка 0..256, определяемая зерном. Удвоенная таблица избавляет от взятия по модулю
+// при хешировании соседних узлов
+fn get_permutation(seed: u64) -> Vec<usize> {
+    let mut rng = get_rng(seed);
+    let mut permutation: Vec<usize> = (0..256).collect();
+    rng.shuffle(&mut permutation);
+    let copy = permutation.clone();
+    permutation.extend(copy);
+    permutation
+}
+
+
+// Двумерный когерентный шум со значением примерно в отрезке [-1, 1]
+pub trait Noise: Send + Sync {
+    fn get(&self, x: f64, y: f64) -> f64;
+}
+
+
+// Градиентный шум Перлина на решетке, перестановка которой определяется зерном
+pub struct PerlinNoise {
+    permutation: Vec<usize>
+}
+
+
+impl PerlinNoise {
+    pub fn new(seed: u64) -> PerlinNoise {
+        PerlinNoise {
+            permutation: get_permutation(seed)
+        }
+    }
+
+    fn hash(&self, x: i64, y: i64) -> usize {
+        self.permutation[self.permutation[(x & 255) as usize] + (y & 255) as usize]
+    }
+
+    fn gradient(&self, x: i64, y: i64, dx: f64, dy: f64) -> f64 {
+        let gradient = GRADIENTS_2D[self.hash(x, y) & 7];
+        gradient.0 * dx + gradient.1 * dy
+    }
+}
+
+
+impl Noise for PerlinNoise {
+    fn get(&self, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (ix, iy) = (x0 as i64, y0 as i64);
+        let (u, v) = (fade(dx), fade(dy));
+        lerp(v,
+             lerp(u, self.gradient(ix, iy, dx, dy), self.gradient(ix + 1, iy, dx - 1.0, dy)),
+             lerp(u, self.gradient(ix, iy + 1, dx, dy - 1.0), self.gradient(ix + 1, iy + 1, dx - 1.0, dy - 1.0)))
+    }
+}
+
+
+// Симплексный шум: решетка из треугольников вместо квадратов, поэтому меньше узлов на точку
+// и нет заметных направленных вдоль осей артефактов
+pub struct SimplexNoise {
+    permutation: Vec<usize>
+}
+
+
+impl SimplexNoise {
+    pub fn new(seed: u64) -> SimplexNoise {
+        SimplexNoise {
+            permutation: get_permutation(seed)
+        }
+    }
+
+    // Вклад вершины треугольника, (dx, dy) - смещение точки от нее
+    fn get_corner(&self, x: i64, y: i64, dx: f64, dy: f64) -> f64 {
+        let t = 0.5 - dx * dx - dy * dy;
+        if t <= 0.0 {
+            return 0.0;
+        }
+        let gradient = GRADIENTS_2D[self.permutation[self.permutation[(x & 255) as usize] + (y & 255) as usize] & 7];
+        t * t * t * t * (gradient.0 * dx + gradient.1 * dy)
+    }
+
+    fn get_corner_3d(&self, x: i64, y: i64, z: i64, dx: f64, dy: f64, dz: f64) -> f64 {
+        let t = 0.6 - dx * dx - dy * dy - dz * dz;
+        if t <= 0.0 {
+            return 0.0;
+        }
+        let hash = self.permutation[self.permutation[self.permutation[(x & 255) as usize] + (y & 255) as usize] + (z & 255) as usize];
+        let gradient = GRADIENTS_3D[hash % 12];
+        t * t * t * t * (gradient.0 * dx + gradient.1 * dy + gradient.2 * dz)
+    }
+
+    // Трехмерный вариант на решетке из тетраэдров, значение примерно в [-1, 1]
+    pub fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (skew, unskew) = (1.0 / 3.0, 1.0 / 6.0);
+        let s = (x + y + z) * skew;
+        let (i, j, k) = ((x + s).floor(), (y + s).floor(), (z + s).floor());
+        let t = (i + j + k) * unskew;
+        let (x0, y0, z0) = (x - (i - t), y - (j - t), z - (k - t));
+        // Куб делится на шесть тетраэдров, нужный определяется порядком координат внутри куба
+        let (first, second) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        let mut result = self.get_corner_3d(i, j, k, x0, y0, z0);
+        for &(offset, corner) in &[(first, 1.0), (second, 2.0)] {
+            let (di, dj, dk) = offset;
+            result += self.get_corner_3d(i + di, j + dj, k + dk,
+                                         x0 - di as f64 + corner * unskew, y0 - dj as f64 + corner * unskew, z0 - dk as f64 + corner * unskew);
+        }
+        result += self.get_corner_3d(i + 1, j + 1, k + 1, x0 - 1.0 + 3.0 * unskew, y0 - 1.0 + 3.0 * unskew, z0 - 1.0 + 3.0 * unskew);
+        32.0 * result
+    }
+}
+
+
+impl Noise for SimplexNoise {
+    fn get(&self, x: f64, y: f64) -> f64 {
+        let skew = 0.5 * (3.0f64.sqrt() - 1.0);
+        let unskew = (3.0 - 3.0f64.sqrt()) / 6.0;
+        // Номер ромба решетки, в который попадает точка после растяжения вдоль диагонали
+        let s = (x + y) * skew;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * unskew;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+        // Ромб делится диагональю на два треугольника
+        let (i1, j1) = if x0 > y0 {(1, 0)} else {(0, 1)};
+        let (x1, y1) = (x0 - i1 as f64 + unskew, y0 - j1 as f64 + unskew);
+        let (x2, y2) = (x0 - 1.0 + 2.0 * unskew, y0 - 1.0 + 2.0 * unskew);
+        let (i, j) = (i as i64, j as i64);
+        70.0 * (self.get_corner(i, j, x0, y0) + self.get_corner(i + i1, j + j1, x1, y1) + self.get_corner(i + 1, j + 1, x2, y2))
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NoiseBasis {
+    Perlin,
+    Simplex
+}
+
+
+impl NoiseBasis {
+    pub fn create(&self, seed: u64) -> Box<dyn Noise> {
+        match *self {
+            NoiseBasis::Perlin => Box::new(PerlinNoise::new(seed)),
+            NoiseBasis::Simplex => Box::new(SimplexNoise::new(seed))
+        }
+    }
+}
+
+
+impl FromStr for NoiseBasis {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<NoiseBasis, String> {
+        match name {
+            "perlin" => Ok(NoiseBasis::Perlin),
+            "simplex" => Ok(NoiseBasis::Simplex),
+            _ => Err(format!("Unknown noise: {}", name))
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FractalKind {
+    // Обычное фрактальное броуновское движение
+    Fbm,
+    // Острые гребни там, где шум проходит через ноль
+    Ridged,
+    // Округлые холмы из модуля шума
+    Billow
+}
+
+
+impl FractalKind {
+    // Переводит значение шума из [-1, 1] в [-1, 1] с формой, характерной для этого вида
+    pub fn apply(&self, value: f64) -> f64 {
+        match *self {
+            FractalKind::Fbm => value,
+            FractalKind::Ridged => {
+                let ridge = 1.0 - value.abs();
+                ridge * ridge * 2.0 - 1.0
+            },
+            FractalKind::Billow => value.abs() * 2.0 - 1.0
+        }
+    }
+}
+
+
+impl FromStr for FractalKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<FractalKind, String> {
+        match name {
+            "fbm" => Ok(FractalKind::Fbm),
+            "ridged" => Ok(FractalKind::Ridged),
+            "billow" => Ok(FractalKind::Billow),
+            _ => Err(format!("Unknown fractal: {}", name))
+        }
+    }
+}
+
+
+// Сумма октав шума: частота каждой следующей в lacunarity раз больше, а амплитуда в gain раз меньше
+#[derive(Copy, Clone, Debug)]
+pub struct Fractal {
+    pub kind: FractalKind,
+    pub octaves: usize,
+    pub lacunarity: f64,
+    pub gain: f64
+}
+
+
+pub const DEFAULT_FRACTAL: Fractal = Fractal {
+    kind: FractalKind::Fbm,
+    octaves: 5,
+    lacunarity: 2.0,
+    gain: 0.5
+};
+
+
+impl Fractal {
+    // sample возвращает шум в точке, растянутой с заданной частотой, поэтому подходит для любой размерности.
+    // Результат нормирован примерно в [-1, 1]
+    pub fn get<F: Fn(f64) -> f64>(&self, sample: F) -> f64 {
+        let mut result = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut total_amplitude = 0.0;
+        for _ in 0..self.octaves {
+            result += amplitude * self.kind.apply(sample(frequency));
+            total_amplitude += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        result / total_amplitude
+    }
+}
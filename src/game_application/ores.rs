@@ -0,0 +1,294 @@
+use game_application::rand::Rng;
+use game_application::cgmath::Point3;
+use game_application::random::{get_rng, get_cell_seed_3d};
+use game_application::chunk::{Chunk, ChunkPosition, CHUNK_SIZE, get_chunk_origin};
+use game_application::material::{BlockId, STONE, COAL, IRON, GOLD};
+use std::str::FromStr;
+
+
+// Участок со своим набором жил совпадает с чанком. Жила короче участка, поэтому хватает соседних
+const ORE_CELL: i32 = CHUNK_SIZE as i32;
+pub const ORES_NUMBER: usize = 3;
+// При большем радиусе жила могла бы выйти за соседний участок
+const MAX_ORE_SIZE: f64 = 4.0;
+// Число жил на участке равно целой части частоты, и без ограничения их перебор мог бы не закончиться
+const MAX_ORE_RARITY: f64 = 64.0;
+
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OreKind {
+    Coal,
+    Iron,
+    Gold
+}
+
+
+impl OreKind {
+    fn get_block(&self) -> BlockId {
+        match *self {
+            OreKind::Coal => COAL,
+            OreKind::Iron => IRON,
+            OreKind::Gold => GOLD
+        }
+    }
+}
+
+
+impl FromStr for OreKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<OreKind, String> {
+        match name {
+            "coal" => Ok(OreKind::Coal),
+            "iron" => Ok(OreKind::Iron),
+            "gold" => Ok(OreKind::Gold),
+            _ => Err(format!("Unknown ore: {}", name))
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct OreParameters {
+    pub kind: OreKind,
+    // Среднее число жил на участок ORE_CELL x ORE_CELL x ORE_CELL
+    pub rarity: f64,
+    // Радиус жилы в вокселях, длина растет вместе с ним
+    pub size: f64,
+    // Глубина под поверхностью в вокселях, на которой начинаются жилы
+    pub min_depth: f64,
+    pub max_depth: f64
+}
+
+
+// Порядок совпадает с вариантами OreKind
+pub const DEFAULT_ORES: [OreParameters; ORES_NUMBER] = [
+    OreParameters {kind: OreKind::Coal, rarity: 6.0, size: 1.5, min_depth: 4.0, max_depth: 80.0},
+    OreParameters {kind: OreKind::Iron, rarity: 3.0, size: 1.2, min_depth: 16.0, max_depth: 120.0},
+    OreParameters {kind: OreKind::Gold, rarity: 0.8, size: 1.0, min_depth: 40.0, max_depth: 200.0}
+];
+
+
+// Формат "название:частота:размер:наименьшая глубина:наибольшая глубина", например "iron:3:1.2:16:120"
+impl FromStr for OreParameters {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<OreParameters, String> {
+        let parts: Vec<&str> = text.split(':').collect();
+        if parts.len() != 5 {
+            return Err(format!("Ore needs name and 4 values: {}", text));
+        }
+        let values = parts[1..].iter().map(|value| value.trim().parse::<f64>()).collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("Wrong ore: {}", text))?;
+        let parameters = OreParameters {
+            kind: parts[0].parse()?,
+            rarity: values[0],
+            size: values[1],
+            min_depth: values[2],
+            max_depth: values[3]
+        };
+        parameters.validate().map_err(|_| format!("Wrong ore: {}", text))?;
+        Ok(parameters)
+    }
+}
+
+
+impl OreParameters {
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [self.rarity, self.size, self.min_depth, self.max_depth];
+        if values.iter().any(|value| !value.is_finite()) {
+            return Err(format!("{:?} ore parameters must be finite", self.kind));
+        }
+        if self.rarity < 0.0 || self.rarity > MAX_ORE_RARITY || self.size <= 0.0 || self.size > MAX_ORE_SIZE || self.max_depth < self.min_depth {
+            return Err(format!("Wrong {:?} ore parameters", self.kind));
+        }
+        Ok(())
+    }
+}
+
+
+// Жилы руды внутри камня. Как и у пещер, каждая жила считается от координат своего участка и зерна,
+// поэтому чанк строится независимо и совпадает с соседями
+pub struct Ores {
+    ores: [OreParameters; ORES_NUMBER],
+    seed: u64
+}
+
+
+impl Ores {
+    pub fn new(seed: u64, ores: &[OreParameters; ORES_NUMBER]) -> Ores {
+        Ores {
+            ores: *ores,
+            seed: seed ^ 0x61C8_8647
+        }
+    }
+
+    // Жилы из участков вокруг чанка, которые могут до него дотянуться
+    fn get_veins<F: Fn(i32, i32) -> Option<f64>>(&self, position: &ChunkPosition, get_height: &F) -> Vec<Vein> {
+        let mut veins = Vec::new();
+        for cell_z in (position.z - 1)..(position.z + 2) {
+            for cell_y in (position.y - 1)..(position.y + 2) {
+                for cell_x in (position.x - 1)..(position.x + 2) {
+                    let cell_seed = get_cell_seed_3d(self.seed, cell_x, cell_y, cell_z);
+                    for (index, ore) in self.ores.iter().enumerate() {
+                        let mut rng = get_rng(cell_seed ^ index as u64);
+                        let mut count = ore.rarity.floor() as usize;
+                        if rng.gen::<f64>() < ore.rarity.fract() {
+                            count += 1;
+                        }
+                        for _ in 0..count {
+                            let start = Point3::new(
+                                cell_x * ORE_CELL + rng.gen_range(0, ORE_CELL),
+                                cell_y * ORE_CELL + rng.gen_range(0, ORE_CELL),
+                                cell_z * ORE_CELL + rng.gen_range(0, ORE_CELL)
+                            );
+                            // У каждой жилы свой генератор, чтобы пропуск одной не сдвигал остальные
+                            let seed = rng.gen::<u64>();
+                            let depth = match get_height(start.x, start.y) {
+                                Some(height) => height - start.z as f64,
+                                None => continue
+                            };
+                            if depth >= ore.min_depth && depth <= ore.max_depth {
+                                veins.push(Vein {
+                                    start,
+                                    ore: *ore,
+                                    seed
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        veins
+    }
+
+    // Руда заменяет только камень
+    pub fn place<F: Fn(i32, i32) -> Option<f64>>(&self, chunk: &mut Chunk, position: &ChunkPosition, get_height: &F) {
+        let origin = get_chunk_origin(position);
+        let size = CHUNK_SIZE as i32;
+        for vein in self.get_veins(position, get_height) {
+            for voxel in vein.get_voxels() {
+                let (local_x, local_y, local_z) = (voxel.x - origin.x, voxel.y - origin.y, voxel.z - origin.z);
+                if local_x < 0 || local_y < 0 || local_z < 0 || local_x >= size || local_y >= size || local_z >= size {
+                    continue;
+                }
+                let (local_x, local_y, local_z) = (local_x as usize, local_y as usize, local_z as usize);
+                if chunk.get_voxel(local_x, local_y, local_z) == STONE {
+                    let density = chunk.get_density(local_x, local_y, local_z);
+                    chunk.set_voxel(local_x, local_y, local_z, vein.ore.kind.get_block());
+                    chunk.set_density(local_x, local_y, local_z, density);
+                }
+            }
+        }
+    }
+}
+
+
+struct Vein {
+    start: Point3<i32>,
+    ore: OreParameters,
+    seed: u64
+}
+
+
+impl Vein {
+    // Жила - короткая извилистая цепочка шаров. Воксели в мировых координатах, не обрезанные по чанку
+    fn get_voxels(&self) -> Vec<Point3<i32>> {
+        let mut rng = get_rng(self.seed);
+        let ore = &self.ore;
+        let (mut x, mut y, mut z) = (self.start.x as f64, self.start.y as f64, self.start.z as f64);
+        let mut direction: (f64, f64, f64) = (rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-0.5, 0.5));
+        let steps = (3.0 * ore.size).ceil() as usize + 1;
+        let mut voxels = Vec::new();
+        for _ in 0..steps {
+            let radius = ore.size * rng.gen_range(0.6, 1.0);
+            let reach = radius.ceil() as i32;
+            for dz in -reach..(reach + 1) {
+                for dy in -reach..(reach + 1) {
+                    for dx in -reach..(reach + 1) {
+                        if ((dx * dx + dy * dy + dz * dz) as f64).sqrt() <= radius {
+                            voxels.push(Point3::new(x.round() as i32 + dx, y.round() as i32 + dy, z.round() as i32 + dz));
+                        }
+                    }
+                }
+            }
+            direction.0 += rng.gen_range(-0.5, 0.5);
+            direction.1 += rng.gen_range(-0.5, 0.5);
+            direction.2 += rng.gen_range(-0.25, 0.25);
+            let length = (direction.0 * direction.0 + direction.1 * direction.1 + direction.2 * direction.2).sqrt().max(1.0e-9);
+            x += direction.0 / length;
+            y += direction.1 / length;
+            z += direction.2 / length;
+        }
+        voxels
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use game_application::cgmath::Point3;
+    use game_application::chunk::{Chunk, CHUNK_SIZE};
+    use game_application::material::{BlockId, STONE};
+    use game_application::ores::{Ores, OreParameters, DEFAULT_ORES};
+
+    fn build_stone_chunk(ores: &Ores, position: &Point3<i32>) -> Chunk {
+        let mut chunk = Chunk::new();
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    chunk.set_voxel(x, y, z, STONE);
+                }
+            }
+        }
+        ores.place(&mut chunk, position, &|_, _| Some(100.0));
+        chunk
+    }
+
+    // Руда, которую жилы соседнего чанка кладут в воксели, первая жила занимает воксель раньше следующих
+    fn get_expected_ores(ores: &Ores, position: &Point3<i32>) -> HashMap<Point3<i32>, BlockId> {
+        let mut expected = HashMap::new();
+        for vein in ores.get_veins(position, &|_, _| Some(100.0)) {
+            for voxel in vein.get_voxels() {
+                expected.entry(voxel).or_insert_with(|| vein.ore.kind.get_block());
+            }
+        }
+        expected
+    }
+
+    #[test]
+    fn test_veins_match_across_chunk_border() {
+        let mut parameters = DEFAULT_ORES;
+        for ore in parameters.iter_mut() {
+            ore.rarity *= 4.0;
+        }
+        let ores = Ores::new(17, &parameters);
+        let (left, right) = (Point3::new(0, 0, 2), Point3::new(1, 0, 2));
+        let chunks = [build_stone_chunk(&ores, &left), build_stone_chunk(&ores, &right)];
+        // Слой x = 31 левого чанка сверяется с жилами правого, слой x = 0 правого - с жилами левого
+        let layers = [(0, CHUNK_SIZE - 1, get_expected_ores(&ores, &right)), (1, 0, get_expected_ores(&ores, &left))];
+        let mut border_ores = 0;
+        for &(index, x, ref expected) in &layers {
+            let origin = [left, right][index] * CHUNK_SIZE as i32;
+            for z in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    let voxel = Point3::new(origin.x + x as i32, origin.y + y as i32, origin.z + z as i32);
+                    let block = chunks[index].get_voxel(x, y, z);
+                    assert_eq!(block, expected.get(&voxel).cloned().unwrap_or(STONE));
+                    if block != STONE {
+                        border_ores += 1;
+                    }
+                }
+            }
+        }
+        assert!(border_ores > 0);
+    }
+
+    #[test]
+    fn test_parse_rejects_oversized_vein() {
+        assert!("iron:3:4:16:120".parse::<OreParameters>().is_ok());
+        assert!("iron:3:4.5:16:120".parse::<OreParameters>().is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use game_application::cgmath::{Point3, Vector3};
+use game_application::camera::Camera;
+use game_application::map::Map;
+
+
+// Размеры тела в вокселях, глаза чуть ниже макушки
+const HALF_WIDTH: f32 = 0.3;
+const STANDING_HEIGHT: f32 = 1.8;
+const CROUCHING_HEIGHT: f32 = 1.4;
+const EYE_DEPTH: f32 = 0.2;
+// Скорости в вокселях за секунду, ускорение - за секунду в квадрате.
+// Прыжок поднимает примерно на 1.35 вокселя, чтобы запрыгнуть на ступень в один воксель с разбега
+const WALK_SPEED: f32 = 4.5;
+const CROUCH_SPEED: f32 = 1.5;
+const JUMP_SPEED: f32 = 9.0;
+const GRAVITY: f32 = 30.0;
+const MAX_FALL_SPEED: f32 = 60.0;
+// Уступ не выше этого тело переступает без прыжка
+const STEP_HEIGHT: f32 = 1.0;
+// Долгий кадр, например при подгрузке чанков, не должен превращаться в огромный шаг
+const MAX_ELAPSED: f32 = 0.1;
+// Касание грани вокселя не считается пересечением с ним
+const EPSILON: f32 = 1.0e-3;
+
+
+// Ограничивающий параллелепипед тела в координатах, где воксель i занимает отрезок [i, i + 1]
+#[derive(Copy, Clone)]
+struct Body {
+    min: [f32; 3],
+    max: [f32; 3]
+}
+
+
+impl Body {
+    fn new(position: Point3<f32>, height: f32) -> Body {
+        Body {
+            min: [position.x - HALF_WIDTH, position.y - HALF_WIDTH, position.z],
+            max: [position.x + HALF_WIDTH, position.y + HALF_WIDTH, position.z + height]
+        }
+    }
+
+    // Воксели, которые тело задевает вдоль оси
+    fn get_range(&self, axis: usize) -> (i32, i32) {
+        ((self.min[axis] + EPSILON).floor() as i32, (self.max[axis] - EPSILON).ceil() as i32)
+    }
+
+    fn is_layer_blocked(&self, map: &Map, axis: usize, layer: i32) -> bool {
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let (u_range, v_range) = (self.get_range(u_axis), self.get_range(v_axis));
+        for u in u_range.0..u_range.1 {
+            for v in v_range.0..v_range.1 {
+                let mut voxel = [0; 3];
+                voxel[axis] = layer;
+                voxel[u_axis] = u;
+                voxel[v_axis] = v;
+                if map.get_voxel(voxel[0], voxel[1], voxel[2]).is_solid() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // Насколько тело может сдвинуться вдоль оси, не войдя в твердый воксель. Слои вокселей проверяются
+    // по порядку, поэтому быстрое тело не проскакивает сквозь тонкую стену. Воксели, в которых тело
+    // уже стоит, не мешают, иначе из них нельзя было бы выбраться
+    fn sweep(&self, map: &Map, axis: usize, delta: f32) -> f32 {
+        if delta > 0.0 {
+            let mut layer = (self.max[axis] - EPSILON).ceil() as i32;
+            while (layer as f32) < self.max[axis] + delta {
+                if self.is_layer_blocked(map, axis, layer) {
+                    return (layer as f32 - self.max[axis]).max(0.0);
+                }
+                layer += 1;
+            }
+        } else if delta < 0.0 {
+            let mut layer = (self.min[axis] + EPSILON).floor() as i32 - 1;
+            while ((layer + 1) as f32) > self.min[axis] + delta {
+                if self.is_layer_blocked(map, axis, layer) {
+                    return ((layer + 1) as f32 - self.min[axis]).min(0.0);
+                }
+                layer -= 1;
+            }
+        }
+        delta
+    }
+}
+
+
+// Игрок в режиме ходьбы: тело падает, прыгает, приседает и не проходит сквозь твердые воксели.
+// В режиме полета камера движется свободно, а тело ждет, пока ходьбу включат снова
+pub struct Player {
+    // Середина нижней грани тела
+    position: Point3<f32>,
+    velocity: Vector3<f32>,
+    on_ground: bool,
+    crouching: bool,
+    walking: bool
+}
+
+
+impl Player {
+    pub fn new() -> Player {
+        Player {
+            position: Point3::new(0.0, 0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            on_ground: false,
+            crouching: false,
+            walking: false
+        }
+    }
+
+    pub fn is_walking(&self) -> bool {
+        self.walking
+    }
+
+    // При переходе к ходьбе тело появляется под камерой так, чтобы глаза остались на месте
+    pub fn toggle_walking(&mut self, map: &Map, camera: &Camera) {
+        self.walking = !self.walking;
+        if self.walking {
+            let voxel_size = map.get_voxel_size();
+            let eye = camera.position;
+            self.position = Point3::new(eye.x / voxel_size + 0.5, eye.z / voxel_size + 0.5,
+                                        eye.y / voxel_size + 0.5 - (STANDING_HEIGHT - EYE_DEPTH));
+            self.velocity = Vector3::new(0.0, 0.0, 0.0);
+            self.on_ground = false;
+            self.crouching = false;
+        }
+    }
+
+    // Пересекается ли тело с вокселем, чтобы не ставить воксели внутрь игрока
+    pub fn overlaps(&self, voxel: &Point3<i32>) -> bool {
+        if !self.walking {
+            return false;
+        }
+        let body = Body::new(self.position, self.get_height());
+        (0..3).all(|axis| {
+            let (start, end) = body.get_range(axis);
+            voxel[axis] >= start && voxel[axis] < end
+        })
+    }
+
+    fn get_height(&self) -> f32 {
+        if self.crouching {CROUCHING_HEIGHT} else {STANDING_HEIGHT}
+    }
+
+    pub fn update(&mut self, map: &Map, camera: &mut Camera, elapsed: f32) {
+        let elapsed = elapsed.min(MAX_ELAPSED);
+        // Пока чанк под ногами строится, на его месте воздух, и тело провалилось бы сквозь рельеф
+        let foot = (self.position.x.floor() as i32, self.position.y.floor() as i32, self.position.z.floor() as i32);
+        if map.is_voxel_ready(foot.0, foot.1, foot.2) && map.is_voxel_ready(foot.0, foot.1, foot.2 - 1) {
+            let (direction, jump, crouch) = camera.get_walk_input();
+            // Встать можно, только если над головой есть место
+            if crouch {
+                self.crouching = true;
+            } else if self.crouching {
+                let body = Body::new(self.position, CROUCHING_HEIGHT);
+                let rise = STANDING_HEIGHT - CROUCHING_HEIGHT;
+                self.crouching = body.sweep(map, 2, rise) < rise;
+            }
+
+            // Направление камеры в мировых осях, где y и z переставлены относительно вокселей
+            let speed = if self.crouching {CROUCH_SPEED} else {WALK_SPEED};
+            self.velocity.x = direction.x * speed;
+            self.velocity.y = direction.z * speed;
+            if jump && self.on_ground {
+                self.velocity.z = JUMP_SPEED;
+            }
+            self.velocity.z = (self.velocity.z - GRAVITY * elapsed).max(-MAX_FALL_SPEED);
+
+            self.move_horizontally(map, self.velocity.x * elapsed, self.velocity.y * elapsed);
+            self.move_vertically(map, self.velocity.z * elapsed);
+        }
+
+        let voxel_size = map.get_voxel_size();
+        let eye_z = self.position.z + self.get_height() - EYE_DEPTH;
+        camera.position = Point3::new((self.position.x - 0.5) * voxel_size, (eye_z - 0.5) * voxel_size,
+                                      (self.position.y - 0.5) * voxel_size);
+    }
+
+    // Сначала вдоль x, потом вдоль y, чтобы тело скользило вдоль стен
+    fn get_moved_position(&self, map: &Map, position: Point3<f32>, dx: f32, dy: f32) -> Point3<f32> {
+        let mut position = position;
+        position.x += Body::new(position, self.get_height()).sweep(map, 0, dx);
+        position.y += Body::new(position, self.get_height()).sweep(map, 1, dy);
+        position
+    }
+
+    // Упершись в уступ, тело пробует подняться на высоту ступени, пройти и опуститься обратно
+    fn move_horizontally(&mut self, map: &Map, dx: f32, dy: f32) {
+        let moved = self.get_moved_position(map, self.position, dx, dy);
+        let get_distance = |position: &Point3<f32>| (position.x - self.position.x).powi(2) + (position.y - self.position.y).powi(2);
+        let blocked = (moved.x - self.position.x - dx).abs() > EPSILON || (moved.y - self.position.y - dy).abs() > EPSILON;
+        if self.on_ground && blocked {
+            let mut raised = self.position;
+            raised.z += Body::new(raised, self.get_height()).sweep(map, 2, STEP_HEIGHT);
+            let mut stepped = self.get_moved_position(map, raised, dx, dy);
+            stepped.z += Body::new(stepped, self.get_height()).sweep(map, 2, self.position.z - raised.z);
+            if get_distance(&stepped) > get_distance(&moved) + EPSILON {
+                self.position = stepped;
+                return;
+            }
+        }
+        self.position = moved;
+    }
+
+    fn move_vertically(&mut self, map: &Map, dz: f32) {
+        let moved = Body::new(self.position, self.get_height()).sweep(map, 2, dz);
+        self.position.z += moved;
+        if moved != dz {
+            self.on_ground = dz < 0.0;
+            self.velocity.z = 0.0;
+        } else {
+            self.on_ground = false;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use game_application::cgmath::{Point3, Vector3};
+    use game_application::camera::Camera;
+    use game_application::map::{Map, get_floor_map};
+    use game_application::material::STONE;
+    use game_application::player::{Player, EPSILON, HALF_WIDTH};
+
+    const VOXEL_SIZE: f32 = 0.5;
+    const ELAPSED: f32 = 1.0 / 60.0;
+
+    // Стена поперек оси x от x = 4 до края пола высотой height вокселей
+    fn add_wall(map: &mut Map, height: i32) {
+        for x in 4..8 {
+            for y in -8..8 {
+                for z in 1..(height + 1) {
+                    map.set_voxel(x, y, z, STONE);
+                }
+            }
+        }
+    }
+
+    fn get_walking_player(x: f32, y: f32, z: f32) -> Player {
+        let mut player = Player::new();
+        player.walking = true;
+        player.position = Point3::new(x, y, z);
+        player
+    }
+
+    // Идет вдоль x стоя на полу, как при нажатой клавише
+    fn walk_along_x(player: &mut Player, map: &Map, frames: usize) {
+        player.on_ground = true;
+        for _ in 0..frames {
+            player.move_horizontally(map, 0.1, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_falls_and_lands_on_floor() {
+        let map = get_floor_map(VOXEL_SIZE);
+        let mut camera = Camera::new(1.0, Point3::new(0.0, 0.0, 0.0));
+        let mut player = get_walking_player(2.5, 2.5, 4.0);
+        for _ in 0..120 {
+            player.update(&map, &mut camera, ELAPSED);
+        }
+        assert!(player.on_ground);
+        assert!((player.position.z - 1.0).abs() < EPSILON);
+        assert_eq!(player.velocity, Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_stops_at_two_voxel_wall() {
+        let mut map = get_floor_map(VOXEL_SIZE);
+        add_wall(&mut map, 2);
+        let mut player = get_walking_player(2.5, 2.5, 1.0);
+        walk_along_x(&mut player, &map, 30);
+        assert!((player.position.x - (4.0 - HALF_WIDTH)).abs() < EPSILON);
+        assert_eq!(player.position.z, 1.0);
+    }
+
+    #[test]
+    fn test_steps_up_one_voxel_ledge() {
+        let mut map = get_floor_map(VOXEL_SIZE);
+        add_wall(&mut map, 1);
+        let mut player = get_walking_player(2.5, 2.5, 1.0);
+        walk_along_x(&mut player, &map, 30);
+        assert!(player.position.x > 4.5);
+        assert!((player.position.z - 2.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_stays_crouched_under_low_ceiling() {
+        let mut camera = Camera::new(1.0, Point3::new(0.0, 0.0, 0.0));
+        for &(ceiling, feet, crouching) in &[(3, 1.5, true), (3, 1.0, false)] {
+            let mut map = get_floor_map(VOXEL_SIZE);
+            for x in -8..8 {
+                for y in -8..8 {
+                    map.set_voxel(x, y, ceiling, STONE);
+                }
+            }
+            // Стоя тело в 1.8 вокселя не помещается под потолком в 1.5 вокселя над ногами, а в 2 помещается
+            let mut player = get_walking_player(2.5, 2.5, feet);
+            player.crouching = true;
+            player.update(&map, &mut camera, ELAPSED);
+            assert_eq!(player.crouching, crouching);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use game_application::rand::{SeedableRng, XorShiftRng};
+
+
+// XorShiftRng не принимает нулевое зерно, поэтому к половинам seed подмешиваются константы
+pub fn get_rng(seed: u64) -> XorShiftRng {
+    let low = seed as u32;
+    let high = (seed >> 32) as u32;
+    XorShiftRng::from_seed([low ^ 0x9E37_79B9, high ^ 0x7F4A_7C15, low ^ 0x85EB_CA6B, high ^ 0xC2B2_AE35])
+}
+
+
+// Зерно участка сетки, у которого свой независимый набор случайных объектов
+pub fn get_cell_seed(seed: u64, x: i32, y: i32) -> u64 {
+    seed ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+}
+
+
+// То же для участка трехмерной сетки
+pub fn get_cell_seed_3d(seed: u64, x: i32, y: i32, z: i32) -> u64 {
+    get_cell_seed(seed, x, y) ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9)
+}
+
+
+// Псевдослучайное число в [0, 1) для столбца
+pub fn get_column_hash(x: i32, y: i32, seed: u64) -> f64 {
+    let mut hash = get_cell_seed(seed, x, y);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    hash ^= hash >> 31;
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
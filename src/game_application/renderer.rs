@@ -0,0 +1,141 @@
+use glium::{self, Surface};
+use glium::backend::Facade;
+use game_application::draw_params::{DrawStatistics, ChunkChange};
+use game_application::chunk::ChunkPosition;
+use game_application::vertex::Vertex;
+use game_application::camera::Camera;
+use game_application::frustum::{BoundingBox, Frustum};
+use game_application::material::{get_transparent_textures, MATERIALS_NUMBER};
+
+use std::collections::HashMap;
+use std::fs;
+
+
+// Непрозрачность прозрачных материалов, сейчас это только вода
+const TRANSPARENT_ALPHA: f32 = 0.6;
+
+
+struct ChunkBuffers {
+    vertices: glium::VertexBuffer<Vertex>,
+    indices: glium::IndexBuffer<u32>,
+    // Прозрачные материалы рисуются отдельным проходом после всего непрозрачного
+    transparent_indices: Option<glium::IndexBuffer<u32>>,
+    bounding_box: BoundingBox
+}
+
+
+// Программы, текстуры и буферы чанков, общие для окна и безоконной отрисовки
+pub struct Renderer {
+    program: glium::Program,
+    transparent_textures: [bool; MATERIALS_NUMBER],
+    chunk_buffers: HashMap<ChunkPosition, ChunkBuffers>
+}
+
+
+fn read_shader(directory: &str, name: &str) -> Result<String, String> {
+    let path = format!("{}/src/shaders/{}", directory, name);
+    fs::read_to_string(&path).map_err(|error| format!("Failed to read shader {}: {}", path, error))
+}
+
+
+// Шейдеры читаются из каталога программы, поэтому неверный каталог или неподходящий OpenGL - ошибка, а не паника
+fn init_textured_triangles_program<F: Facade>(display: &F, directory: &str) -> Result<glium::Program, String> {
+    let vertex_shader_src = read_shader(directory, "textured_triangles.vert")?;
+    let fragment_shader_src = read_shader(directory, "materials.frag")?;
+    glium::Program::from_source(display, &vertex_shader_src, &fragment_shader_src, None)
+        .map_err(|error| format!("Failed to compile shaders: {}", error))
+}
+
+
+impl Renderer {
+    pub fn new<F: Facade>(display: &F, directory: &str) -> Result<Renderer, String> {
+        Ok(Renderer {
+            program: init_textured_triangles_program(display, directory)?,
+            transparent_textures: get_transparent_textures(),
+            chunk_buffers: HashMap::new()
+        })
+    }
+
+    pub fn apply_changes<F: Facade>(&mut self, display: &F, changes: Vec<ChunkChange>) {
+        for change in changes {
+            match change {
+                ChunkChange::Updated(position, vertices, indices) => {
+                    let transparent_textures = &self.transparent_textures;
+                    let (transparent_indices, indices): (Vec<_>, Vec<_>) = indices.chunks(3)
+                        .partition(|triangle| transparent_textures[vertices[triangle[0] as usize].material as usize]);
+                    let transparent_indices: Vec<u32> = transparent_indices.concat();
+                    self.chunk_buffers.insert(position, ChunkBuffers {
+                        vertices: glium::VertexBuffer::new(display, &vertices).unwrap(),
+                        indices: glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &indices.concat()).unwrap(),
+                        transparent_indices: if transparent_indices.is_empty() {
+                            None
+                        } else {
+                            Some(glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &transparent_indices).unwrap())
+                        },
+                        bounding_box: BoundingBox::from_vertices(&vertices)
+                    });
+                },
+                ChunkChange::Removed(position) => {
+                    self.chunk_buffers.remove(&position);
+                }
+            }
+        }
+    }
+
+    pub fn draw<S: Surface>(&self, target: &mut S, camera: &Camera) -> DrawStatistics {
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                .. Default::default()
+            },
+            //backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockWise,
+            .. Default::default()
+        };
+        let view = camera.get_view();
+        let perspective = camera.get_perspective();
+        let light = [0.5, 0.5, 0.5f32];
+        let model = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0f32]
+        ];
+        target.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
+        let frustum = Frustum::new(&perspective, &view);
+        let mut statistics = DrawStatistics::default();
+        let mut visible_buffers = Vec::with_capacity(self.chunk_buffers.len());
+        for buffers in self.chunk_buffers.values() {
+            if !frustum.intersects(&buffers.bounding_box) {
+                statistics.culled_chunks += 1;
+                continue;
+            }
+            target.draw(&buffers.vertices, &buffers.indices, &self.program,
+                        &uniform!{model: model, view: view, perspective: perspective, u_light: light, u_alpha: 1.0f32},
+                        &params).unwrap();
+            statistics.draw_calls += 1;
+            statistics.triangles += buffers.indices.len() / 3;
+            visible_buffers.push(buffers);
+        }
+        // Прозрачные материалы не пишут глубину, чтобы сквозь один слой воды было видно другой и дно
+        let transparent_params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: false,
+                .. Default::default()
+            },
+            blend: glium::Blend::alpha_blending(),
+            .. Default::default()
+        };
+        for buffers in visible_buffers {
+            if let Some(ref transparent_indices) = buffers.transparent_indices {
+                target.draw(&buffers.vertices, transparent_indices, &self.program,
+                            &uniform!{model: model, view: view, perspective: perspective, u_light: light, u_alpha: TRANSPARENT_ALPHA},
+                            &transparent_params).unwrap();
+                statistics.draw_calls += 1;
+                statistics.triangles += transparent_indices.len() / 3;
+            }
+        }
+        statistics
+    }
+}
@@ -0,0 +1,20 @@
+use game_application::mesher::Mesher;
+use game_application::heightmap::HeightScale;
+use game_application::terrain::{Generator, TerrainParameters};
+
+
+pub struct Settings {
+    pub seed: u64,
+    pub world_path: Option<String>,
+    pub mesher: Mesher,
+    pub infinite: bool,
+    pub view_distance: i32,
+    pub show_statistics: bool,
+    // Без упрощения дальних чанков, например для экспорта
+    pub lod: bool,
+    // PNG, из которого строится конечная карта вместо алмаза-квадрата
+    pub heightmap: Option<String>,
+    pub height_scale: HeightScale,
+    pub generator: Generator,
+    pub terrain: TerrainParameters
+}
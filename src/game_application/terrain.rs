@@ -0,0 +1,318 @@
+use game_application::chunk::{Chunk, ChunkPosition, CHUNK_SIZE, get_chunk_origin};
+use game_application::noise::{Noise, NoiseBasis, Fractal, DEFAULT_FRACTAL};
+use game_application::caves::{Caves, CaveParameters, DEFAULT_CAVES};
+use game_application::biomes::{Biome, BiomeMap};
+use game_application::erosion::{ErosionParameters, NO_EROSION};
+use game_application::water::{WaterParameters, WaterLevels, DEFAULT_WATER};
+use game_application::features::{Features, FeatureParameters, DEFAULT_FEATURES, MAX_FEATURE_HEIGHT};
+use game_application::ores::{Ores, OreParameters, DEFAULT_ORES, ORES_NUMBER};
+use game_application::random::get_column_hash;
+use game_application::material::{WATER, BEDROCK};
+use std::str::FromStr;
+
+
+pub const BASE_HEIGHT: f64 = 64.0;
+const HEIGHT_AMPLITUDE: f64 = 48.0;
+// Размер самых крупных холмов в вокселях
+const HILL_SIZE: f64 = 128.0;
+// Насколько море по умолчанию ниже среднего уровня рельефа
+pub const SEA_DEPTH: f64 = 16.0;
+// Нижние слои мира - неразрушимая порода, выше нижнего она перемежается с камнем
+const BEDROCK_THICKNESS: i32 = 3;
+// Подмешивается к зерну мира, чтобы узор бедрока не совпадал с другими случайными стадиями
+const BEDROCK_SEED: u64 = 0x3C6E_F372;
+
+
+// Заполняет чанк по высотам столбцов, get_height возвращает None за границей карты.
+// Стадия caves сдвигает поверхность и вырезает пещеры, биомы выбирают материалы слоев,
+// а без них везде равнина. В самом низу лежит бедрок, который пещеры не трогают, его узор задает bedrock_seed.
+// Над поверхностью до уровня get_water_level стоит вода. z_limit ограничивает высоту мира сверху
+fn fill_chunk<F, W>(position: &ChunkPosition, get_height: F, get_water_level: W, caves: &Caves, biomes: Option<&BiomeMap>,
+                    bedrock_seed: u64, z_limit: i32) -> Chunk
+    where F: Fn(i32, i32) -> Option<f64>, W: Fn(i32, i32) -> Option<f64> {
+    let mut chunk = Chunk::new();
+    let origin = get_chunk_origin(position);
+    let cave_field = caves.get_field(position, &get_height);
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            let (world_x, world_y) = (origin.x + x as i32, origin.y + y as i32);
+            let exact_height = match get_height(world_x, world_y) {
+                Some(height) => height,
+                None => continue
+            };
+            let biome = biomes.map_or(Biome::Plains, |biomes| biomes.get_biome(world_x, world_y));
+            let water_level = get_water_level(world_x, world_y).map_or(i32::MIN, |level| level.round() as i32);
+            // Под водой пещеры не вырезаются, иначе у дна оставались бы воздушные карманы
+            let flooded = water_level > exact_height.round() as i32;
+            for z in 0..CHUNK_SIZE {
+                let world_z = origin.z + z as i32;
+                if world_z >= z_limit {
+                    break;
+                }
+                let surface_height = caves.get_surface_height(world_x, world_y, world_z, exact_height);
+                let bedrock = world_z < BEDROCK_THICKNESS
+                    && get_column_hash(world_x, world_y, bedrock_seed ^ (world_z as u64).wrapping_mul(0x1656_67B1_9E37_79F9))
+                        < 1.0 - world_z as f64 / BEDROCK_THICKNESS as f64;
+                let cave_density = if flooded || bedrock {f64::INFINITY} else {cave_field.get(x, y, z)};
+                let depth = surface_height.round() as i32 - world_z;
+                if depth >= 0 && bedrock {
+                    chunk.set_voxel(x, y, z, BEDROCK);
+                } else if depth >= 0 && cave_density >= 0.0 {
+                    chunk.set_voxel(x, y, z, biome.get_material(depth));
+                } else if depth < 0 && world_z <= water_level {
+                    chunk.set_voxel(x, y, z, WATER);
+                }
+                // Поверхность проходит на полвокселя выше центра верхнего вокселя столбца
+                chunk.set_density(x, y, z, (surface_height + 0.5 - world_z as f64).min(cave_density) as f32);
+            }
+        }
+    }
+    chunk
+}
+
+
+// Строит любой чанк независимо от остальных, поэтому может работать в фоновых потоках
+pub trait TerrainGenerator: Send + Sync {
+    fn build_chunk(&self, position: &ChunkPosition) -> Chunk;
+}
+
+
+// Способ построения конечной карты. Бесконечная всегда строится шумом
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Generator {
+    // Алмаз-квадрат, только на квадратной сетке 2 ^ i + 1
+    DiamondSquare,
+    Noise
+}
+
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Generator, String> {
+        match name {
+            "diamond-square" => Ok(Generator::DiamondSquare),
+            "noise" => Ok(Generator::Noise),
+            _ => Err(format!("Unknown generator: {}", name))
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct NoiseParameters {
+    pub basis: NoiseBasis,
+    pub fractal: Fractal
+}
+
+
+pub const DEFAULT_NOISE: NoiseParameters = NoiseParameters {
+    basis: NoiseBasis::Perlin,
+    fractal: DEFAULT_FRACTAL
+};
+
+
+impl NoiseParameters {
+    // Без октав фрактал делит на нулевую сумму амплитуд, и все высоты становятся NaN
+    pub fn validate(&self) -> Result<(), String> {
+        let fractal = &self.fractal;
+        if fractal.octaves == 0 {
+            return Err("Octaves number must be positive".to_string());
+        }
+        if !fractal.lacunarity.is_finite() || !fractal.gain.is_finite() {
+            return Err("Wrong fractal parameters".to_string());
+        }
+        Ok(())
+    }
+}
+
+
+// Все, что определяет рельеф, кроме зерна и поля высот
+#[derive(Copy, Clone, Debug)]
+pub struct TerrainParameters {
+    pub noise: NoiseParameters,
+    pub caves: CaveParameters,
+    pub biomes: bool,
+    // Бесконечная карта строится по чанкам, и эрозия применяется только к полю высот конечной
+    pub erosion: ErosionParameters,
+    pub water: WaterParameters,
+    pub features: FeatureParameters,
+    pub ores: [OreParameters; ORES_NUMBER]
+}
+
+
+pub const DEFAULT_TERRAIN: TerrainParameters = TerrainParameters {
+    noise: DEFAULT_NOISE,
+    caves: DEFAULT_CAVES,
+    biomes: true,
+    erosion: NO_EROSION,
+    water: DEFAULT_WATER,
+    features: DEFAULT_FEATURES,
+    ores: DEFAULT_ORES
+};
+
+
+impl TerrainParameters {
+    // Можно ли строить рельеф по отдельным чанкам, или сначала нужно поле высот всей карты
+    pub fn is_chunk_local(&self) -> bool {
+        !self.erosion.is_enabled() && !self.water.needs_height_field()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.noise.validate()?;
+        self.caves.validate()?;
+        self.erosion.validate()?;
+        self.water.validate()?;
+        self.features.validate()?;
+        self.ores.iter().try_for_each(|ore| ore.validate())
+    }
+}
+
+
+// Рельеф конечной карты по заранее посчитанному полю высот, индексируемому как [y][x].
+// Поле не обязано быть квадратным, например если оно загружено из изображения
+pub struct HeightFieldTerrain {
+    height_field: Vec<Vec<f64>>,
+    z_limit: i32,
+    water_levels: WaterLevels,
+    caves: Caves,
+    biomes: Option<BiomeMap>,
+    ores: Ores,
+    features: Features,
+    bedrock_seed: u64
+}
+
+
+impl HeightFieldTerrain {
+    pub fn new(height_field: Vec<Vec<f64>>, z_limit: usize, seed: u64, parameters: &TerrainParameters) -> HeightFieldTerrain {
+        HeightFieldTerrain {
+            height_field,
+            z_limit: z_limit as i32,
+            water_levels: Vec::new(),
+            caves: Caves::new(seed, &parameters.caves),
+            biomes: if parameters.biomes {Some(BiomeMap::new(seed))} else {None},
+            ores: Ores::new(seed, &parameters.ores),
+            features: Features::new(seed, &parameters.features),
+            bedrock_seed: seed ^ BEDROCK_SEED
+        }
+    }
+
+    fn get_height(&self, x: i32, y: i32) -> Option<f64> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.height_field.get(y as usize).and_then(|row| row.get(x as usize)).cloned()
+    }
+
+    // Уровни воды над столбцами того же поля высот
+    pub fn set_water_levels(&mut self, water_levels: WaterLevels) {
+        self.water_levels = water_levels;
+    }
+
+    fn get_water_level(&self, x: i32, y: i32) -> Option<f64> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.water_levels.get(y as usize).and_then(|row| row.get(x as usize)).cloned().and_then(|level| level)
+    }
+}
+
+
+impl TerrainGenerator for HeightFieldTerrain {
+    fn build_chunk(&self, position: &ChunkPosition) -> Chunk {
+        let mut chunk = fill_chunk(position, |x, y| self.get_height(x, y), |x, y| self.get_water_level(x, y), &self.caves,
+                                   self.biomes.as_ref(), self.bedrock_seed, self.z_limit);
+        self.ores.place(&mut chunk, position, &|x, y| self.get_height(x, y));
+        let get_column = |x, y| self.get_height(x, y).map(|height| (height, self.get_water_level(x, y)));
+        self.features.decorate(&mut chunk, position, get_column, &self.caves, self.biomes.as_ref(), self.z_limit);
+        chunk
+    }
+}
+
+
+// Бесконечный рельеф: высота каждого столбца считается независимо от остальных,
+// поэтому любой чанк можно построить отдельно, и он совпадет с соседями
+pub struct NoiseTerrain {
+    noise: Box<dyn Noise>,
+    fractal: Fractal,
+    sea_level: Option<f64>,
+    caves: Caves,
+    biomes: Option<BiomeMap>,
+    ores: Ores,
+    features: Features,
+    bedrock_seed: u64
+}
+
+
+impl NoiseTerrain {
+    pub fn new(seed: u64, parameters: &TerrainParameters) -> NoiseTerrain {
+        NoiseTerrain {
+            noise: parameters.noise.basis.create(seed),
+            fractal: parameters.noise.fractal,
+            sea_level: parameters.water.get_sea_level(Some(BASE_HEIGHT - SEA_DEPTH)),
+            caves: Caves::new(seed, &parameters.caves),
+            biomes: if parameters.biomes {Some(BiomeMap::new(seed))} else {None},
+            ores: Ores::new(seed, &parameters.ores),
+            features: Features::new(seed, &parameters.features),
+            bedrock_seed: seed ^ BEDROCK_SEED
+        }
+    }
+
+    pub fn get_height(&self, x: i32, y: i32) -> f64 {
+        let (noise_x, noise_y) = (x as f64 / HILL_SIZE, y as f64 / HILL_SIZE);
+        let value = self.fractal.get(|frequency| self.noise.get(noise_x * frequency, noise_y * frequency));
+        match self.biomes {
+            Some(ref biomes) => biomes.get_height(x, y, BASE_HEIGHT, value),
+            None => BASE_HEIGHT + HEIGHT_AMPLITUDE * value
+        }
+    }
+
+    // Номер самого верхнего слоя чанков, в котором может оказаться поверхность, вода или деревья
+    pub fn get_max_chunk_z(&self) -> i32 {
+        let amplitude = if self.biomes.is_some() {BiomeMap::get_max_height_offset()} else {HEIGHT_AMPLITUDE};
+        let max_height = (BASE_HEIGHT + amplitude + self.caves.get_max_overhang() + MAX_FEATURE_HEIGHT as f64)
+            .max(self.sea_level.unwrap_or(0.0));
+        ((max_height + 1.0) / CHUNK_SIZE as f64).ceil() as i32
+    }
+}
+
+
+impl TerrainGenerator for NoiseTerrain {
+    fn build_chunk(&self, position: &ChunkPosition) -> Chunk {
+        let mut chunk = fill_chunk(position, |x, y| Some(self.get_height(x, y)), |_, _| self.sea_level, &self.caves,
+                                   self.biomes.as_ref(), self.bedrock_seed, i32::MAX);
+        self.ores.place(&mut chunk, position, &|x, y| Some(self.get_height(x, y)));
+        let get_column = |x, y| Some((self.get_height(x, y), self.sea_level));
+        self.features.decorate(&mut chunk, position, get_column, &self.caves, self.biomes.as_ref(), i32::MAX);
+        chunk
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_application::cgmath::Point3;
+
+    // Где в нижнем чанке ровной карты лежит бедрок
+    fn get_bedrock(seed: u64) -> Vec<bool> {
+        let terrain = HeightFieldTerrain::new(vec![vec![20.0; CHUNK_SIZE]; CHUNK_SIZE], CHUNK_SIZE, seed, &DEFAULT_TERRAIN);
+        let chunk = terrain.build_chunk(&Point3::new(0, 0, 0));
+        let mut bedrock = Vec::new();
+        for z in 0..BEDROCK_THICKNESS as usize {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    bedrock.push(chunk.get_voxel(x, y, z) == BEDROCK);
+                }
+            }
+        }
+        bedrock
+    }
+
+    #[test]
+    fn test_bedrock_depends_on_seed() {
+        assert!(get_bedrock(1).iter().any(|&bedrock| bedrock));
+        assert!(get_bedrock(1) == get_bedrock(1));
+        assert!(get_bedrock(1) != get_bedrock(2));
+    }
+}
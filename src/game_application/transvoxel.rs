@@ -0,0 +1,280 @@
+use game_application::cgmath::Point3;
+use game_application::transvoxel_data::transition_cell_data::{TRANSITION_CELL_CLASS, TRANSITION_CELL_DATA, TRANSITION_VERTEX_DATA};
+use game_application::vertex::Vertex;
+use game_application::mesh::Mesh;
+use game_application::chunk::{Neighborhood, CHUNK_SIZE};
+use game_application::marching_cubes::{TRIANGLE_TABLE, CORNERS, EDGE_CORNERS};
+use game_application::material::BlockId;
+
+
+pub const MAX_LOD_LEVEL: usize = 3;
+
+// Доля крайней клетки, которую занимает переходная клетка у грани с более детальным соседом
+const TRANSITION_WIDTH: f32 = 0.5;
+
+// Биты номера случая для отсчетов полной грани переходной клетки, нумерация как у Ленгиела:
+// 6 7 8
+// 3 4 5
+// 0 1 2
+const TRANSITION_CASE_BITS: [usize; 9] = [0x01, 0x02, 0x04, 0x80, 0x100, 0x08, 0x40, 0x20, 0x10];
+
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Lod {
+    // Шаг выборки равен 2 ^ level вокселей
+    pub level: usize,
+    // Биты граней, за которыми сосед на уровень детальнее, см. get_face_bit
+    pub transitions: u8
+}
+
+
+pub const FULL_DETAIL: Lod = Lod {
+    level: 0,
+    transitions: 0
+};
+
+
+// side равен 0 для грани с меньшей координатой и 1 для противоположной
+pub fn get_face_bit(axis: usize, side: usize) -> u8 {
+    1 << (2 * axis + side)
+}
+
+
+struct LodMesher<'a> {
+    neighborhood: &'a Neighborhood,
+    lod: Lod,
+    step: i32,
+    smooth: bool,
+    mesh: Mesh
+}
+
+
+impl<'a> LodMesher<'a> {
+    fn get_sample(&self, point: &Point3<i32>) -> (BlockId, f32) {
+        let block = self.neighborhood.get_voxel(point.x, point.y, point.z);
+        // Без сглаживания вершина должна оказаться в середине ребра, как у process_cube
+        let density = if self.smooth {
+            self.neighborhood.get_density(point.x, point.y, point.z)
+        } else if block.is_solid() {
+            1.0
+        } else {
+            -1.0
+        };
+        (block, density)
+    }
+
+    // Сжимает крайний слой клеток у граней с переходными клетками, освобождая им место.
+    // Координаты удвоенные и локальные относительно чанка
+    fn shrink(&self, mut position: Point3<f32>) -> Point3<f32> {
+        let cell = 2.0 * self.step as f32;
+        let size = 2.0 * CHUNK_SIZE as f32;
+        for axis in 0..3 {
+            if self.lod.transitions & get_face_bit(axis, 0) != 0 && position[axis] < cell {
+                position[axis] = cell * TRANSITION_WIDTH + position[axis] * (1.0 - TRANSITION_WIDTH);
+            }
+            if self.lod.transitions & get_face_bit(axis, 1) != 0 && position[axis] > size - cell {
+                position[axis] = size - cell * TRANSITION_WIDTH - (size - position[axis]) * (1.0 - TRANSITION_WIDTH);
+            }
+        }
+        position
+    }
+
+    fn add_triangle(&mut self, edges: &[(Point3<i32>, Point3<i32>, bool)]) {
+        let origin = self.neighborhood.get_origin();
+        let mut points = Vec::new();
+        let mut positions = Vec::new();
+        let mut materials = Vec::new();
+        for &(start, end, shrink) in edges {
+            let (start_block, start_density) = self.get_sample(&start);
+            let (end_block, end_density) = self.get_sample(&end);
+            let t = start_density / (start_density - end_density);
+            let mut position = Point3::new(
+                2.0 * start.x as f32 + 2.0 * t * (end.x - start.x) as f32,
+                2.0 * start.y as f32 + 2.0 * t * (end.y - start.y) as f32,
+                2.0 * start.z as f32 + 2.0 * t * (end.z - start.z) as f32
+            );
+            if shrink {
+                position = self.shrink(position);
+            }
+            // Сумма концов ребра - его середина в удвоенных координатах, она и служит идентификатором вершины
+            points.push(Point3::new(2 * origin.x + start.x + end.x, 2 * origin.y + start.y + end.y, 2 * origin.z + start.z + end.z));
+            positions.push(Point3::new(position.x + 2.0 * origin.x as f32,
+                                       position.y + 2.0 * origin.y as f32,
+                                       position.z + 2.0 * origin.z as f32));
+            materials.push(if start_density > 0.0 {start_block} else {end_block});
+        }
+        self.mesh.add_triangle(&points, &positions, &materials);
+    }
+
+    fn process_regular_cell(&mut self, x: i32, y: i32, z: i32) {
+        let corners: Vec<Point3<i32>> = CORNERS.iter()
+            .map(|corner| Point3::new(x + corner.0 * self.step, y + corner.1 * self.step, z + corner.2 * self.step))
+            .collect();
+        let mut cube_index = 0;
+        for (i, corner) in corners.iter().enumerate() {
+            if self.get_sample(corner).1 > 0.0 {
+                cube_index |= 1 << i;
+            }
+        }
+        let mut index = 0;
+        while TRIANGLE_TABLE[cube_index][index] != -1 {
+            let edges: Vec<(Point3<i32>, Point3<i32>, bool)> = (0..3).map(|i| {
+                let (start, end) = EDGE_CORNERS[TRIANGLE_TABLE[cube_index][index + i] as usize];
+                (corners[start], corners[end], true)
+            }).collect();
+            self.add_triangle(&edges);
+            index += 3;
+        }
+    }
+
+    // Переходная клетка сшивает полную грань соседа (9 отсчетов с шагом step / 2)
+    // с гранью собственной клетки (4 угла с шагом step), сдвинутой внутрь чанка
+    fn process_transition_cell(&mut self, axis: usize, side: usize, u: i32, v: i32) {
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let half = self.step / 2;
+        let mut samples = Vec::with_capacity(13);
+        for i in 0..9 {
+            let mut point = Point3::new(0, 0, 0);
+            point[axis] = side as i32 * CHUNK_SIZE as i32;
+            point[u_axis] = u + (i % 3) * half;
+            point[v_axis] = v + (i / 3) * half;
+            samples.push(point);
+        }
+        for &i in &[0, 2, 6, 8] {
+            let corner = samples[i];
+            samples.push(corner);
+        }
+
+        let mut case = 0;
+        for (i, bit) in TRANSITION_CASE_BITS.iter().enumerate() {
+            if self.get_sample(&samples[i]).1 > 0.0 {
+                case |= bit;
+            }
+        }
+        let class = TRANSITION_CELL_CLASS[case];
+        let data = &TRANSITION_CELL_DATA[(class & 0x7F) as usize];
+        let edges: Vec<(Point3<i32>, Point3<i32>, bool)> = TRANSITION_VERTEX_DATA[case].iter()
+            .take(data.get_vertex_count() as usize)
+            .map(|code| {
+                let (start, end) = (((code >> 4) & 0xF) as usize, (code & 0xF) as usize);
+                // Отсчеты 9-12 принадлежат грани обычной клетки и сдвигаются вместе с ней
+                (samples[start], samples[end], start >= 9)
+            }).collect();
+        // У нижней грани обход в таблицах противоположен принятому в marching_cubes,
+        // у верхней базис (u, v, внутрь) меняет ориентацию
+        let reverse = (class & 0x80 != 0) != (side == 0);
+        for triangle in data.vertex_index.chunks(3).take(data.get_triangle_count() as usize) {
+            let order = if reverse {[triangle[0], triangle[2], triangle[1]]} else {[triangle[0], triangle[1], triangle[2]]};
+            let triangle_edges: Vec<(Point3<i32>, Point3<i32>, bool)> = order.iter().map(|&i| edges[i as usize]).collect();
+            self.add_triangle(&triangle_edges);
+        }
+    }
+}
+
+
+// Меш чанка с шагом выборки 2 ^ lod.level. Со стороны граней из lod.transitions добавляются
+// переходные клетки Transvoxel, совпадающие на границе с мешем соседа уровнем детальнее
+pub fn get_vertices(neighborhood: &Neighborhood, lod: Lod, smooth: bool, voxel_size: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let step = 1 << lod.level;
+    let mut mesher = LodMesher {
+        neighborhood,
+        lod,
+        step,
+        smooth,
+        mesh: Mesh::new()
+    };
+    let size = CHUNK_SIZE as i32;
+    for x in (0..size).step_by(step as usize) {
+        for y in (0..size).step_by(step as usize) {
+            for z in (0..size).step_by(step as usize) {
+                mesher.process_regular_cell(x, y, z);
+            }
+        }
+    }
+    if lod.level > 0 {
+        for axis in 0..3 {
+            for side in 0..2 {
+                if lod.transitions & get_face_bit(axis, side) == 0 {
+                    continue;
+                }
+                for u in (0..size).step_by(step as usize) {
+                    for v in (0..size).step_by(step as usize) {
+                        mesher.process_transition_cell(axis, side, u, v);
+                    }
+                }
+            }
+        }
+    }
+    mesher.mesh.get_indexed_vertices(voxel_size)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use game_application::cgmath::{Point3, Vector3};
+    use game_application::map::Map;
+    use game_application::mesher::Mesher;
+    use game_application::vertex::Vertex;
+    use game_application::material::STONE;
+    use game_application::transvoxel::{Lod, FULL_DETAIL, get_face_bit};
+
+    // При единичном размере вокселя мировые координаты вершин совпадают с координатами вокселей
+    const VOXEL_SIZE: f32 = 1.0;
+    const BOUNDARY: f32 = 32.0;
+
+    // Пологий склон через два соседних по x чанка
+    fn get_slope_map() -> Map {
+        let mut map = Map::new(VOXEL_SIZE, 64, Point3::new(0.0, 0.0, 0.0));
+        for x in -1..66 {
+            for y in -1..34 {
+                let height = 10 + (x + y) / 8;
+                for z in 0..(height + 1) {
+                    map.set_voxel(x, y, z, STONE);
+                }
+            }
+        }
+        map
+    }
+
+    fn get_boundary_points(vertices: &[Vertex]) -> Vec<[u32; 3]> {
+        let mut points: Vec<[u32; 3]> = vertices.iter()
+            .filter(|vertex| vertex.position[0] == BOUNDARY)
+            .map(|vertex| [vertex.position[0].to_bits(), vertex.position[1].to_bits(), vertex.position[2].to_bits()])
+            .collect();
+        points.sort();
+        points.dedup();
+        points
+    }
+
+    // Знак вертикальной составляющей нормали, заданной обходом треугольника
+    fn get_facing(vertices: &[Vertex], indices: &[u32]) -> Vec<f32> {
+        indices.chunks(3).map(|triangle| {
+            let get_point = |i: usize| {
+                let position = vertices[triangle[i] as usize].position;
+                Vector3::new(position[0], position[1], position[2])
+            };
+            (get_point(1) - get_point(0)).cross(get_point(2) - get_point(0)).y
+        }).collect()
+    }
+
+    #[test]
+    fn test_transition_face_matches_finer_neighbor() {
+        let map = get_slope_map();
+        let (fine_vertices, fine_indices) = Mesher::MarchingCubes
+            .get_lod_vertices(&map.get_neighborhood(&Point3::new(0, 0, 0)), FULL_DETAIL, VOXEL_SIZE);
+        // Более детальный сосед лежит со стороны меньших x
+        let lod = Lod {level: 1, transitions: get_face_bit(0, 0)};
+        let (coarse_vertices, coarse_indices) = Mesher::MarchingCubes
+            .get_lod_vertices(&map.get_neighborhood(&Point3::new(1, 0, 0)), lod, VOXEL_SIZE);
+
+        let fine_points = get_boundary_points(&fine_vertices);
+        assert!(!fine_points.is_empty());
+        assert!(fine_points == get_boundary_points(&coarse_vertices));
+
+        // Все треугольники обоих мешей, включая переходные, обходятся в одну сторону
+        let fine_facing = get_facing(&fine_vertices, &fine_indices);
+        let sign = fine_facing[0].signum();
+        assert!(fine_facing.iter().all(|facing| facing * sign > 0.0));
+        assert!(get_facing(&coarse_vertices, &coarse_indices).iter().all(|facing| facing * sign >= 0.0));
+    }
+}
@@ -1,4 +1,5 @@
 use game_application::cgmath::{Vector3, Point3};
+use game_application::material::BlockId;
 
 
 #[derive(Copy, Clone)]
@@ -6,16 +7,18 @@ pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub material: u32
 }
 
 impl Vertex {
-    pub fn new(point: &Point3<usize>, normal: &Vector3<f32>, tex_coords: &[f32; 2], voxel_size: f32) -> Vertex {
+    pub fn new(point: &Point3<f32>, normal: &Vector3<f32>, tex_coords: &[f32; 2], block: &BlockId, voxel_size: f32) -> Vertex {
         Vertex{
-            position: [(point.x as f32) * voxel_size / 2.0, (point.z as f32) * voxel_size / 2.0, (point.y as f32) * voxel_size / 2.0],
+            position: [point.x * voxel_size / 2.0, point.z * voxel_size / 2.0, point.y * voxel_size / 2.0],
             normal: [normal.x, normal.y, normal.z],
-            tex_coords: *tex_coords
+            tex_coords: *tex_coords,
+            material: block.get_material().texture_index
         }
     }
 }
 
-implement_vertex!(Vertex, position, normal, tex_coords);
+implement_vertex!(Vertex, position, normal, tex_coords, material);
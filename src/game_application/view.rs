@@ -1,34 +1,18 @@
-use glium::{self, Surface};
+use glium;
 use game_application::glutin;
-use game_application::draw_params::DrawParams;
+use game_application::draw_params::{DrawParams, DrawStatistics};
 use game_application::camera::Camera;
+use game_application::renderer::Renderer;
 use game_application::cgmath::Point3;
 
 use std::fs::File;
 use std::io::prelude::*;
 
-extern crate image;
-use std::io::Cursor;
 
-
-pub struct View<'time> {
+pub struct View {
     pub camera: Camera,
-    directory: &'time str,
     display: glium::Display,
-    texture: glium::texture::SrgbTexture2d,
-    normal_map: glium::texture::Texture2d
-}
-
-
-fn init_textured_triangles_program(display: &glium::Display, directory: &str) -> glium::Program {
-    let mut vertex_shader_file = File::open(directory.to_string() + "/src/shaders/textured_triangles.vert").unwrap();
-    //let mut fragment_shader_file = File::open(directory.to_string() + "/src/shaders/textured_triangles.frag").unwrap();
-    let mut fragment_shader_file = File::open(directory.to_string() + "/src/shaders/green_color.frag").unwrap();
-    let mut vertex_shader_src = String::new();
-    let mut fragment_shader_src = String::new();
-    vertex_shader_file.read_to_string(&mut vertex_shader_src).expect("Failed to read vertex shader file!");
-    fragment_shader_file.read_to_string(&mut fragment_shader_src).expect("Failed to read fragment shader file!");
-    glium::Program::from_source(display, &vertex_shader_src, &fragment_shader_src, None).unwrap()
+    renderer: Renderer
 }
 
 
@@ -43,80 +27,33 @@ fn init_points_program(display: &glium::Display, directory: &str) -> glium::Prog
 }
 
 
-fn init_textures(display: &glium::Display) -> (glium::texture::SrgbTexture2d, glium::texture::Texture2d) {
-    let image = image::load(Cursor::new(&include_bytes!("../../images/grass_texture.jpg")[..]), image::JPEG).unwrap().to_rgba();
-    let image_dimensions = image.dimensions();
-    let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    let texture = glium::texture::SrgbTexture2d::new(display, image).unwrap();
-
-    let image = image::load(Cursor::new(&include_bytes!("../../images/grass_normal_map.png")[..]), image::PNG).unwrap().to_rgba();
-    let image_dimensions = image.dimensions();
-    let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    let normal_map = glium::texture::Texture2d::new(display, image).unwrap();
-
-    (texture, normal_map)
-}
-
-
-impl<'time> View<'time> {
-    pub fn new(events_loop: &glutin::EventsLoop, directory: &'time str, camera_position: Point3<f32>) -> View<'time>  {
+impl View {
+    pub fn new(events_loop: &glutin::EventsLoop, directory: &str, camera_position: Point3<f32>) -> Result<View, String> {
         let window = glutin::WindowBuilder::new().with_decorations(false).with_fullscreen(Some(events_loop.get_primary_monitor()));
         let context = glutin::ContextBuilder::new().with_depth_buffer(24);
         let display = glium::Display::new(window, context, &events_loop).unwrap();
 
-        // textires init
-        let (texture, normal_map) = init_textures(&display);
+        // shaders and textures init
+        //let program = init_points_program(&display, directory);
+        let renderer = Renderer::new(&display, directory)?;
 
         // camera init
         let (width, height) = display.get_framebuffer_dimensions();
         let aspect_ratio = width as f32 / height as f32;
         let camera = Camera::new(aspect_ratio, camera_position);
-        View{
+        Ok(View{
             camera: camera,
-            directory: directory,
             display: display,
-            texture: texture,
-            normal_map: normal_map
-        }
+            renderer
+        })
     }
 
-    pub fn draw(&self, draw_params: DrawParams) {
-        //let program = init_points_program(&self.display, &self.directory);
-        let program = init_textured_triangles_program(&self.display, &self.directory);
-        let shape = glium::vertex::VertexBuffer::new(&self.display, &draw_params.shape).unwrap();
-        //let indices = glium::IndexBuffer::new(&self.display, glium::index::PrimitiveType::TrianglesList, &draw_params.indices).unwrap();
-        //let indices = glium::index::NoIndices(glium::index::PrimitiveType::Points);
-        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
-
-        let params = glium::DrawParameters {
-            depth: glium::Depth {
-                test: glium::draw_parameters::DepthTest::IfLess,
-                write: true,
-                .. Default::default()
-            },
-            //backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockWise,
-            .. Default::default()
-        };
+    pub fn draw(&mut self, draw_params: DrawParams) -> DrawStatistics {
+        self.renderer.apply_changes(&self.display, draw_params.changes);
         let mut target = self.display.draw();
-        let view = self.camera.get_view();
-        let perspective = self.camera.get_perspective();
-        let light = [0.5, 0.5, 0.5f32];
-        let model = [
-            [1.0, 0.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [0.0, 0.0, 0.0, 1.0f32]
-        ];
-        target.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
-        /*
-        target.draw(&shape, &indices, &program,
-                    &uniform!{model: model, view: view, perspective: perspective},
-                    &params).unwrap();
-        */
-        target.draw(&shape, &indices, &program,
-                    &uniform!{model: model, view: view, perspective: perspective, diffuse_tex: &self.texture, normal_tex: &self.normal_map, u_light: light},
-                    &params).unwrap();
+        let statistics = self.renderer.draw(&mut target, &self.camera);
         target.finish().unwrap();
+        statistics
     }
 
     pub fn handle_event(&mut self, event: &glutin::WindowEvent) {
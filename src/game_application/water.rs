@@ -0,0 +1,387 @@
+use game_application::rand::Rng;
+use game_application::random::get_rng;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+
+// Лужи мельче этого не заполняются водой
+const MIN_LAKE_DEPTH: f64 = 2.0;
+// Истоки рек выбираются среди столбцов выше этой доли от перепада высот карты
+const MIN_SOURCE_HEIGHT: f64 = 0.6;
+const MAX_SOURCE_ATTEMPTS: usize = 100;
+// Короткие ручьи не прорезаются
+const MIN_RIVER_LENGTH: usize = 16;
+// Русло прорезается кистью такого радиуса, и широкая кисть обходила бы всю карту на каждом шаге реки
+const MAX_RIVER_SIZE: f64 = 64.0;
+// Ни одна карта не бывает выше, а до уровня моря строятся все слои чанков бесконечной карты
+const MAX_SEA_LEVEL: f64 = 1024.0;
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct WaterParameters {
+    pub sea: bool,
+    // Уровень моря в вокселях, без него у каждого генератора свой
+    pub sea_level: Option<f64>,
+    pub lakes: bool,
+    pub rivers: usize,
+    // Полуширина и глубина русла в вокселях
+    pub river_width: f64,
+    pub river_depth: f64
+}
+
+
+pub const DEFAULT_WATER: WaterParameters = WaterParameters {
+    sea: true,
+    sea_level: None,
+    lakes: true,
+    rivers: 8,
+    river_width: 2.0,
+    river_depth: 2.0
+};
+
+
+pub const NO_WATER: WaterParameters = WaterParameters {
+    sea: false,
+    lakes: false,
+    rivers: 0,
+    .. DEFAULT_WATER
+};
+
+
+impl WaterParameters {
+    // Уровень моря с учетом того, включено ли оно вообще
+    pub fn get_sea_level(&self, default_level: Option<f64>) -> Option<f64> {
+        if self.sea {self.sea_level.or(default_level)} else {None}
+    }
+
+    // Озера и реки ищутся по всей карте сразу, поэтому бывают только у конечной
+    pub fn needs_height_field(&self) -> bool {
+        self.lakes || self.rivers > 0
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let is_river_size = |size: f64| size > 0.0 && size <= MAX_RIVER_SIZE;
+        if !is_river_size(self.river_width) || !is_river_size(self.river_depth) {
+            return Err("Wrong river parameters".to_string());
+        }
+        if self.sea_level.is_some_and(|level| !(-MAX_SEA_LEVEL..=MAX_SEA_LEVEL).contains(&level)) {
+            return Err("Wrong sea level".to_string());
+        }
+        Ok(())
+    }
+}
+
+
+// Элемент очереди затопления, первой достается самая низкая клетка
+#[derive(PartialEq)]
+struct FloodCell {
+    level: f64,
+    index: usize
+}
+
+
+impl Eq for FloodCell {}
+
+
+impl Ord for FloodCell {
+    fn cmp(&self, other: &FloodCell) -> Ordering {
+        // BinaryHeap отдает наибольший элемент, поэтому порядок обратный. Номер клетки делает порядок однозначным
+        other.level.partial_cmp(&self.level).unwrap_or(Ordering::Equal).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &FloodCell) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+// Заполнение впадин (priority flood): вода стекает за край карты, и каждая клетка поднимается
+// до наинизшего уровня, через который из нее можно вытечь. Заодно для каждой клетки запоминается,
+// куда она стекает, и эти ссылки всегда ведут к краю
+struct Drainage {
+    width: usize,
+    length: usize,
+    filled: Vec<f64>,
+    downstream: Vec<Option<usize>>
+}
+
+
+impl Drainage {
+    fn new(height_field: &[Vec<f64>]) -> Drainage {
+        let (width, length) = (height_field[0].len(), height_field.len());
+        let get_height = |index: usize| height_field[index / width][index % width];
+        let mut filled = vec![0.0; width * length];
+        let mut downstream = vec![None; width * length];
+        let mut visited = vec![false; width * length];
+        let mut queue = BinaryHeap::new();
+        for y in 0..length {
+            for x in 0..width {
+                if x == 0 || y == 0 || x == width - 1 || y == length - 1 {
+                    let index = x + y * width;
+                    filled[index] = get_height(index);
+                    visited[index] = true;
+                    queue.push(FloodCell {level: filled[index], index});
+                }
+            }
+        }
+        while let Some(FloodCell {level, index}) = queue.pop() {
+            let (x, y) = (index % width, index / width);
+            let neighbors = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+            for &(neighbor_x, neighbor_y) in &neighbors {
+                if neighbor_x >= width || neighbor_y >= length {
+                    continue;
+                }
+                let neighbor = neighbor_x + neighbor_y * width;
+                if visited[neighbor] {
+                    continue;
+                }
+                visited[neighbor] = true;
+                filled[neighbor] = get_height(neighbor).max(level);
+                downstream[neighbor] = Some(index);
+                queue.push(FloodCell {level: filled[neighbor], index: neighbor});
+            }
+        }
+        Drainage {
+            width,
+            length,
+            filled,
+            downstream
+        }
+    }
+
+    fn get_neighbors(&self, index: usize) -> Vec<usize> {
+        let (x, y) = (index % self.width, index / self.width);
+        let mut neighbors = Vec::with_capacity(4);
+        if x > 0 {
+            neighbors.push(index - 1);
+        }
+        if x + 1 < self.width {
+            neighbors.push(index + 1);
+        }
+        if y > 0 {
+            neighbors.push(index - self.width);
+        }
+        if y + 1 < self.length {
+            neighbors.push(index + self.width);
+        }
+        neighbors
+    }
+
+    // Озеро - связная область поднятых клеток. Его уровень общий, а заполняется оно только достаточно глубоким
+    fn fill_lakes(&self, height_field: &[Vec<f64>], levels: &mut [Vec<Option<f64>>]) {
+        let get_depth = |index: usize| self.filled[index] - height_field[index / self.width][index % self.width];
+        let mut visited = vec![false; self.filled.len()];
+        for start in 0..self.filled.len() {
+            if visited[start] || get_depth(start) <= 0.0 {
+                continue;
+            }
+            visited[start] = true;
+            let mut lake = vec![start];
+            let mut queue = VecDeque::new();
+            queue.push_back(start);
+            while let Some(index) = queue.pop_front() {
+                for neighbor in self.get_neighbors(index) {
+                    if !visited[neighbor] && get_depth(neighbor) > 0.0 {
+                        visited[neighbor] = true;
+                        lake.push(neighbor);
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+            if lake.iter().map(|&index| get_depth(index)).fold(0.0, f64::max) < MIN_LAKE_DEPTH {
+                continue;
+            }
+            for index in lake {
+                let level = &mut levels[index / self.width][index % self.width];
+                *level = Some(level.map_or(self.filled[index], |level| level.max(self.filled[index])));
+            }
+        }
+    }
+
+    // Путь воды от клетки до края карты или моря
+    fn trace(&self, source: usize, sea_level: f64) -> Vec<usize> {
+        let mut path = vec![source];
+        let mut index = source;
+        while let Some(next) = self.downstream[index] {
+            if self.filled[next] < sea_level {
+                break;
+            }
+            path.push(next);
+            index = next;
+        }
+        path
+    }
+
+    // Русло прорезается на river_depth ниже уровня воды, который вдоль реки не растет,
+    // и плавно выходит к берегам на расстоянии river_width
+    fn carve_river(&self, path: &[usize], parameters: &WaterParameters, height_field: &mut [Vec<f64>],
+                   river_levels: &mut [Vec<Option<f64>>]) {
+        let radius = parameters.river_width.max(0.5);
+        let reach = radius.ceil() as i32;
+        let mut level = f64::INFINITY;
+        for &index in path {
+            level = level.min(self.filled[index]);
+            let (center_x, center_y) = ((index % self.width) as i32, (index / self.width) as i32);
+            for dy in -reach..(reach + 1) {
+                for dx in -reach..(reach + 1) {
+                    let (x, y) = (center_x + dx, center_y + dy);
+                    let distance = ((dx * dx + dy * dy) as f64).sqrt();
+                    if x < 0 || y < 0 || x >= self.width as i32 || y >= self.length as i32 || distance > radius {
+                        continue;
+                    }
+                    let (x, y) = (x as usize, y as usize);
+                    let bed = level - parameters.river_depth * (1.0 - (distance / radius).powi(2));
+                    height_field[y][x] = height_field[y][x].min(bed);
+                    // Где русла сходятся, берется нижний уровень, чтобы вода не поднималась над соседним руслом
+                    let river_level = &mut river_levels[y][x];
+                    *river_level = Some(river_level.map_or(level, |river_level| river_level.min(level)));
+                }
+            }
+        }
+    }
+}
+
+
+// Уровень воды для каждого столбца поля высот [y][x], None - воды нет
+pub type WaterLevels = Vec<Vec<Option<f64>>>;
+
+
+// Реки прорезают поле высот. Вода стоит над столбцом, только если уровень выше его поверхности
+pub fn add_water(height_field: &mut [Vec<f64>], parameters: &WaterParameters, sea_level: Option<f64>, seed: u64) -> WaterLevels {
+    let length = height_field.len();
+    let width = height_field.first().map_or(0, |row| row.len());
+    let mut levels = vec![vec![sea_level; width]; length];
+    if width < 3 || length < 3 || !parameters.needs_height_field() {
+        return levels;
+    }
+    let drainage = Drainage::new(height_field);
+    if parameters.lakes {
+        drainage.fill_lakes(height_field, &mut levels);
+    }
+    if parameters.rivers == 0 {
+        return levels;
+    }
+
+    let min_height = drainage.filled.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max_height = drainage.filled.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let source_height = min_height + MIN_SOURCE_HEIGHT * (max_height - min_height);
+    let mut rng = get_rng(seed ^ 0x2C1B_3C6D);
+    let mut river_levels = vec![vec![None; width]; length];
+    for _ in 0..parameters.rivers {
+        // Истоком становится первая подходящая случайная точка на возвышенности
+        for _ in 0..MAX_SOURCE_ATTEMPTS {
+            let source = rng.gen_range(0, width * length);
+            if drainage.filled[source] < source_height {
+                continue;
+            }
+            let path = drainage.trace(source, sea_level.unwrap_or(f64::NEG_INFINITY));
+            if path.len() >= MIN_RIVER_LENGTH {
+                drainage.carve_river(&path, parameters, height_field, &mut river_levels);
+                break;
+            }
+        }
+    }
+    for (row, river_row) in levels.iter_mut().zip(river_levels.iter()) {
+        for (level, river_level) in row.iter_mut().zip(river_row.iter()) {
+            *level = match (*level, *river_level) {
+                (Some(level), Some(river_level)) => Some(level.max(river_level)),
+                (level, river_level) => level.or(river_level)
+            };
+        }
+    }
+    levels
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_application::diamond_square::generate_height_field;
+
+    const LAKES_ONLY: WaterParameters = WaterParameters {
+        rivers: 0,
+        .. DEFAULT_WATER
+    };
+
+    const RIVERS_ONLY: WaterParameters = WaterParameters {
+        lakes: false,
+        rivers: 1,
+        river_width: 1.0,
+        river_depth: 2.0,
+        .. DEFAULT_WATER
+    };
+
+    // Плоское дно высотой 0 за стенкой высотой 10, в которой одна щель высотой 7
+    fn get_bowl() -> Vec<Vec<f64>> {
+        let mut height_field = vec![vec![0.0; 9]; 9];
+        for (y, row) in height_field.iter_mut().enumerate() {
+            for (x, height) in row.iter_mut().enumerate() {
+                if x == 0 || y == 0 || x == 8 || y == 8 {
+                    *height = 10.0;
+                }
+            }
+        }
+        height_field[0][4] = 7.0;
+        height_field
+    }
+
+    // Склон, который поднимается вдоль x
+    fn get_slope(size: usize) -> Vec<Vec<f64>> {
+        (0..size).map(|_| (0..size).map(|x| x as f64).collect()).collect()
+    }
+
+    #[test]
+    fn test_bowl_fills_to_rim() {
+        let mut height_field = get_bowl();
+        let levels = add_water(&mut height_field, &LAKES_ONLY, None, 1);
+        assert!(height_field == get_bowl());
+        for (y, row) in levels.iter().enumerate() {
+            for (x, level) in row.iter().enumerate() {
+                let inside = x > 0 && y > 0 && x < 8 && y < 8;
+                assert_eq!(*level, if inside {Some(7.0)} else {None});
+            }
+        }
+    }
+
+    #[test]
+    fn test_slope_has_no_lakes() {
+        let mut height_field = get_slope(16);
+        let levels = add_water(&mut height_field, &LAKES_ONLY, None, 1);
+        assert!(levels.iter().all(|row| row.iter().all(|level| level.is_none())));
+    }
+
+    #[test]
+    fn test_river_is_carved_down_to_sea() {
+        let sea_level = 5.0;
+        let original = get_slope(64);
+        let mut height_field = original.clone();
+        let levels = add_water(&mut height_field, &RIVERS_ONLY, Some(sea_level), 1);
+        let mut carved_columns = Vec::new();
+        for y in 0..64 {
+            for x in 0..64 {
+                if height_field[y][x] < original[y][x] {
+                    carved_columns.push(x);
+                    // Русло не выше воды в нем, а вода не ниже моря
+                    let level = levels[y][x].unwrap();
+                    assert!(level >= sea_level && level >= height_field[y][x]);
+                }
+            }
+        }
+        // Русло доходит до моря, но не прорезает его дно
+        assert!(carved_columns.contains(&(sea_level as usize)));
+        assert!(carved_columns.iter().all(|&x| x >= sea_level as usize));
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_water() {
+        let get_water = |seed: u64| {
+            let mut height_field = generate_height_field(65, 3);
+            let levels = add_water(&mut height_field, &DEFAULT_WATER, Some(64.0), seed);
+            (height_field, levels)
+        };
+        assert!(get_water(7) == get_water(7));
+    }
+}
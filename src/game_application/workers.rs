@@ -0,0 +1,163 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread::{self, JoinHandle};
+use game_application::cgmath::Point3;
+use game_application::chunk::{Chunk, ChunkPosition, Neighborhood};
+use game_application::mesher::Mesher;
+use game_application::transvoxel::Lod;
+use game_application::terrain::TerrainGenerator;
+use game_application::vertex::Vertex;
+
+
+pub enum Job {
+    Generate(ChunkPosition, Arc<dyn TerrainGenerator>),
+    // version позволяет отбросить результат, если чанк успел измениться еще раз
+    Mesh(Neighborhood, Mesher, Lod, f32, u64)
+}
+
+
+pub enum JobResult {
+    Generated(ChunkPosition, Chunk),
+    Meshed(ChunkPosition, u64, Vec<Vertex>, Vec<u32>),
+    // Задача упала с паникой. Для построения меша передается его номер
+    Failed(ChunkPosition, Option<u64>)
+}
+
+
+impl Job {
+    fn get_position(&self) -> ChunkPosition {
+        match *self {
+            Job::Generate(position, _) => position,
+            Job::Mesh(ref neighborhood, ..) => neighborhood.position
+        }
+    }
+
+    // Паника в задаче не должна оставить чанк навсегда недостроенным, поэтому вместо нее отправляется Failed
+    fn run_safely(self) -> JobResult {
+        let position = self.get_position();
+        let version = match self {
+            Job::Generate(..) => None,
+            Job::Mesh(.., version) => Some(version)
+        };
+        panic::catch_unwind(AssertUnwindSafe(|| self.run())).unwrap_or(JobResult::Failed(position, version))
+    }
+
+    fn run(self) -> JobResult {
+        match self {
+            Job::Generate(position, generator) => JobResult::Generated(position, generator.build_chunk(&position)),
+            Job::Mesh(neighborhood, mesher, lod, voxel_size, version) => {
+                let (vertices, indices) = mesher.get_lod_vertices(&neighborhood, lod, voxel_size);
+                JobResult::Meshed(neighborhood.position, version, vertices, indices)
+            }
+        }
+    }
+}
+
+
+struct JobQueue {
+    jobs: Vec<Job>,
+    // Чанк, в котором находится камера: первыми выполняются ближайшие к нему задачи
+    center: ChunkPosition,
+    stopped: bool
+}
+
+
+impl JobQueue {
+    fn pop_nearest(&mut self) -> Option<Job> {
+        let center = self.center;
+        let get_distance = |job: &Job| {
+            let position = job.get_position();
+            (position.x - center.x).pow(2) + (position.y - center.y).pow(2) + (position.z - center.z).pow(2)
+        };
+        let nearest = (0..self.jobs.len()).min_by_key(|&i| get_distance(&self.jobs[i]))?;
+        Some(self.jobs.swap_remove(nearest))
+    }
+}
+
+
+fn run_worker(queue: Arc<(Mutex<JobQueue>, Condvar)>, results: Sender<JobResult>) {
+    let (ref lock, ref condvar) = *queue;
+    loop {
+        let job = {
+            let mut queue = lock.lock().unwrap();
+            loop {
+                if queue.stopped {
+                    return;
+                }
+                if let Some(job) = queue.pop_nearest() {
+                    break job;
+                }
+                queue = condvar.wait(queue).unwrap();
+            }
+        };
+        if results.send(job.run_safely()).is_err() {
+            return;
+        }
+    }
+}
+
+
+pub struct WorkerPool {
+    queue: Arc<(Mutex<JobQueue>, Condvar)>,
+    results: Receiver<JobResult>,
+    workers: Vec<JoinHandle<()>>
+}
+
+
+impl WorkerPool {
+    pub fn new() -> WorkerPool {
+        // Один поток остается отрисовке
+        let threads_number = thread::available_parallelism().map(|number| number.get()).unwrap_or(2).max(2) - 1;
+        let queue = Arc::new((Mutex::new(JobQueue {
+            jobs: Vec::new(),
+            center: Point3::new(0, 0, 0),
+            stopped: false
+        }), Condvar::new()));
+        let (sender, receiver) = channel();
+        let workers = (0..threads_number).map(|_| {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            thread::spawn(move || run_worker(queue, sender))
+        }).collect();
+        WorkerPool {
+            queue,
+            results: receiver,
+            workers
+        }
+    }
+
+    pub fn submit(&self, job: Job) {
+        let (ref lock, ref condvar) = *self.queue;
+        lock.lock().unwrap().jobs.push(job);
+        condvar.notify_one();
+    }
+
+    pub fn set_center(&self, center: ChunkPosition) {
+        self.queue.0.lock().unwrap().center = center;
+    }
+
+    // Убирает из очереди еще не начатые задачи для выгруженных чанков
+    pub fn cancel<F: Fn(&ChunkPosition) -> bool>(&self, predicate: F) {
+        self.queue.0.lock().unwrap().jobs.retain(|job| !predicate(&job.get_position()));
+    }
+
+    pub fn get_results(&self) -> Vec<JobResult> {
+        self.results.try_iter().collect()
+    }
+}
+
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        {
+            let (ref lock, ref condvar) = *self.queue;
+            lock.lock().unwrap().stopped = true;
+            condvar.notify_all();
+        }
+        // Упавший поток уже сообщил о панике, ронять из-за него еще и деструктор незачем
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,495 @@
+use std::io::{self, Read, Write};
+use game_application::cgmath::Point3;
+use game_application::chunk::{Chunk, ChunkPosition, CHUNK_SIZE, CHUNK_VOLUME};
+use game_application::material::BlockId;
+use game_application::terrain::{TerrainParameters, NoiseParameters};
+use game_application::noise::{NoiseBasis, Fractal, FractalKind};
+use game_application::caves::CaveParameters;
+use game_application::erosion::ErosionParameters;
+use game_application::water::WaterParameters;
+use game_application::features::FeatureParameters;
+use game_application::ores::{OreParameters, OreKind, ORES_NUMBER};
+
+
+const MAGIC: &[u8; 4] = b"VXCW";
+// Версия 2 добавила плотности вокселей, версия 3 - параметры рельефа: шум, пещеры, биомы,
+// эрозию, воду, объекты на поверхности и руды
+const VERSION: u32 = 3;
+// Худший случай RLE: каждая серия длиной в один байт занимает три
+const MAX_BLOCK_LENGTH: usize = 3 * CHUNK_VOLUME;
+
+
+pub struct WorldHeader {
+    pub seed: u64,
+    pub voxel_size: f32,
+    // Нулевой размер означает бесконечную карту, в файле хранятся только измененные чанки
+    pub map_size: usize,
+    // С ними бесконечная карта после загрузки строит новые чанки так же, как до сохранения
+    pub terrain: TerrainParameters,
+    pub chunks_number: usize
+}
+
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buffer = [0; 1];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+
+fn read_bool<R: Read>(reader: &mut R) -> io::Result<bool> {
+    match read_u8(reader)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid_data("Wrong flag"))
+    }
+}
+
+
+fn write_f64<W: Write>(writer: &mut W, value: f64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+
+fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(i32::from_le_bytes(buffer))
+}
+
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+
+// Числа с плавающей точкой пишутся как f64, счетчики как u32, флаги и виды одним байтом
+fn write_terrain<W: Write>(writer: &mut W, terrain: &TerrainParameters) -> io::Result<()> {
+    let noise = &terrain.noise;
+    writer.write_all(&[noise.basis as u8, noise.fractal.kind as u8])?;
+    write_u32(writer, noise.fractal.octaves as u32)?;
+    write_f64(writer, noise.fractal.lacunarity)?;
+    write_f64(writer, noise.fractal.gain)?;
+
+    let caves = &terrain.caves;
+    for value in &[caves.tunnels, caves.caverns, caves.size, caves.min_depth, caves.max_depth, caves.overhangs] {
+        write_f64(writer, *value)?;
+    }
+    writer.write_all(&[terrain.biomes as u8])?;
+
+    let erosion = &terrain.erosion;
+    write_u32(writer, erosion.droplets as u32)?;
+    for value in &[erosion.inertia, erosion.capacity, erosion.erosion_rate, erosion.deposition_rate, erosion.evaporation_rate] {
+        write_f64(writer, *value)?;
+    }
+    write_u32(writer, erosion.thermal_iterations as u32)?;
+    write_f64(writer, erosion.talus)?;
+    write_f64(writer, erosion.thermal_rate)?;
+
+    let water = &terrain.water;
+    writer.write_all(&[water.sea as u8, water.sea_level.is_some() as u8])?;
+    write_f64(writer, water.sea_level.unwrap_or(0.0))?;
+    writer.write_all(&[water.lakes as u8])?;
+    write_u32(writer, water.rivers as u32)?;
+    write_f64(writer, water.river_width)?;
+    write_f64(writer, water.river_depth)?;
+
+    let features = &terrain.features;
+    write_f64(writer, features.spacing)?;
+    writer.write_all(&[features.trees as u8, features.boulders as u8, features.ruins as u8])?;
+
+    for ore in &terrain.ores {
+        writer.write_all(&[ore.kind as u8])?;
+        for value in &[ore.rarity, ore.size, ore.min_depth, ore.max_depth] {
+            write_f64(writer, *value)?;
+        }
+    }
+    Ok(())
+}
+
+
+fn read_terrain<R: Read>(reader: &mut R) -> io::Result<TerrainParameters> {
+    let basis = match read_u8(reader)? {
+        0 => NoiseBasis::Perlin,
+        1 => NoiseBasis::Simplex,
+        _ => return Err(invalid_data("Unknown noise basis"))
+    };
+    let kind = match read_u8(reader)? {
+        0 => FractalKind::Fbm,
+        1 => FractalKind::Ridged,
+        2 => FractalKind::Billow,
+        _ => return Err(invalid_data("Unknown fractal kind"))
+    };
+    let noise = NoiseParameters {
+        basis,
+        fractal: Fractal {
+            kind,
+            octaves: read_u32(reader)? as usize,
+            lacunarity: read_f64(reader)?,
+            gain: read_f64(reader)?
+        }
+    };
+    let caves = CaveParameters {
+        tunnels: read_f64(reader)?,
+        caverns: read_f64(reader)?,
+        size: read_f64(reader)?,
+        min_depth: read_f64(reader)?,
+        max_depth: read_f64(reader)?,
+        overhangs: read_f64(reader)?
+    };
+    let biomes = read_bool(reader)?;
+    let erosion = ErosionParameters {
+        droplets: read_u32(reader)? as usize,
+        inertia: read_f64(reader)?,
+        capacity: read_f64(reader)?,
+        erosion_rate: read_f64(reader)?,
+        deposition_rate: read_f64(reader)?,
+        evaporation_rate: read_f64(reader)?,
+        thermal_iterations: read_u32(reader)? as usize,
+        talus: read_f64(reader)?,
+        thermal_rate: read_f64(reader)?
+    };
+    let sea = read_bool(reader)?;
+    let has_sea_level = read_bool(reader)?;
+    let sea_level = read_f64(reader)?;
+    let water = WaterParameters {
+        sea,
+        sea_level: if has_sea_level {Some(sea_level)} else {None},
+        lakes: read_bool(reader)?,
+        rivers: read_u32(reader)? as usize,
+        river_width: read_f64(reader)?,
+        river_depth: read_f64(reader)?
+    };
+    let features = FeatureParameters {
+        spacing: read_f64(reader)?,
+        trees: read_bool(reader)?,
+        boulders: read_bool(reader)?,
+        ruins: read_bool(reader)?
+    };
+    let mut ores = Vec::with_capacity(ORES_NUMBER);
+    for _ in 0..ORES_NUMBER {
+        let kind = match read_u8(reader)? {
+            0 => OreKind::Coal,
+            1 => OreKind::Iron,
+            2 => OreKind::Gold,
+            _ => return Err(invalid_data("Unknown ore"))
+        };
+        ores.push(OreParameters {
+            kind,
+            rarity: read_f64(reader)?,
+            size: read_f64(reader)?,
+            min_depth: read_f64(reader)?,
+            max_depth: read_f64(reader)?
+        });
+    }
+    let terrain = TerrainParameters {
+        noise,
+        caves,
+        biomes,
+        erosion,
+        water,
+        features,
+        ores: [ores[0], ores[1], ores[2]]
+    };
+    // Испорченный файл не должен давать рельеф, который не разрешили бы аргументы
+    terrain.validate().map_err(|message| invalid_data(&message))?;
+    Ok(terrain)
+}
+
+
+pub fn write_header<W: Write>(writer: &mut W, header: &WorldHeader) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u32(writer, VERSION)?;
+    writer.write_all(&header.seed.to_le_bytes())?;
+    writer.write_all(&header.voxel_size.to_le_bytes())?;
+    write_u32(writer, header.map_size as u32)?;
+    write_u32(writer, CHUNK_SIZE as u32)?;
+    write_terrain(writer, &header.terrain)?;
+    write_u32(writer, header.chunks_number as u32)
+}
+
+
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<WorldHeader> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not a world file"));
+    }
+    if read_u32(reader)? != VERSION {
+        return Err(invalid_data("Unsupported world file version"));
+    }
+    let seed = read_u64(reader)?;
+    let voxel_size = f32::from_bits(read_u32(reader)?);
+    let map_size = read_u32(reader)? as usize;
+    if read_u32(reader)? as usize != CHUNK_SIZE {
+        return Err(invalid_data("World file has different chunk size"));
+    }
+    let terrain = read_terrain(reader)?;
+    let chunks_number = read_u32(reader)? as usize;
+    Ok(WorldHeader {
+        seed,
+        voxel_size,
+        map_size,
+        terrain,
+        chunks_number
+    })
+}
+
+
+// Данные чанка сжимаются RLE: пары (длина серии u16, байт значения)
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let mut end = start + 1;
+        while end < bytes.len() && bytes[end] == bytes[start] && end - start < u16::MAX as usize {
+            end += 1;
+        }
+        data.extend_from_slice(&((end - start) as u16).to_le_bytes());
+        data.push(bytes[start]);
+        start = end;
+    }
+    data
+}
+
+
+fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(CHUNK_VOLUME);
+    for run in data.chunks(3) {
+        if run.len() != 3 {
+            return Err(invalid_data("Truncated chunk data"));
+        }
+        let length = u16::from_le_bytes([run[0], run[1]]) as usize;
+        // Испорченный файл не должен раздувать чанк сверх его размера
+        if bytes.len() + length > CHUNK_VOLUME {
+            return Err(invalid_data("Wrong chunk size"));
+        }
+        bytes.resize(bytes.len() + length, run[2]);
+    }
+    Ok(bytes)
+}
+
+
+fn write_block<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    let data = compress(bytes);
+    write_u32(writer, data.len() as u32)?;
+    writer.write_all(&data)
+}
+
+
+fn read_block<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    // Длина проверяется до выделения памяти под блок
+    let length = read_u32(reader)? as usize;
+    if length > MAX_BLOCK_LENGTH {
+        return Err(invalid_data("Chunk data is too long"));
+    }
+    let mut data = vec![0; length];
+    reader.read_exact(&mut data)?;
+    decompress(&data)
+}
+
+
+// Сжатое представление чанка, в таком же виде чанки лежат в файле после координат
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let voxels: Vec<u8> = chunk.get_voxels().iter().map(|block| block.0).collect();
+    let densities: Vec<u8> = chunk.get_densities().iter().map(|density| *density as u8).collect();
+    let mut data = Vec::new();
+    // Запись в Vec не может завершиться ошибкой
+    write_block(&mut data, &voxels).unwrap();
+    write_block(&mut data, &densities).unwrap();
+    data
+}
+
+
+pub fn decode_chunk<R: Read>(reader: &mut R) -> io::Result<Chunk> {
+    let mut voxels = Vec::new();
+    for byte in read_block(reader)? {
+        match BlockId::from_byte(byte) {
+            Some(block) => voxels.push(block),
+            None => return Err(invalid_data("Unknown material"))
+        }
+    }
+    let densities = read_block(reader)?.iter().map(|density| *density as i8).collect();
+    Chunk::from_data(voxels, densities).ok_or_else(|| invalid_data("Wrong chunk size"))
+}
+
+
+pub fn write_encoded_chunk<W: Write>(writer: &mut W, position: &ChunkPosition, data: &[u8]) -> io::Result<()> {
+    for coordinate in &[position.x, position.y, position.z] {
+        writer.write_all(&coordinate.to_le_bytes())?;
+    }
+    writer.write_all(data)
+}
+
+
+pub fn write_chunk<W: Write>(writer: &mut W, position: &ChunkPosition, chunk: &Chunk) -> io::Result<()> {
+    write_encoded_chunk(writer, position, &encode_chunk(chunk))
+}
+
+
+pub fn read_chunk<R: Read>(reader: &mut R) -> io::Result<(ChunkPosition, Chunk)> {
+    let position = Point3::new(read_i32(reader)?, read_i32(reader)?, read_i32(reader)?);
+    Ok((position, decode_chunk(reader)?))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use game_application::cgmath::Point3;
+    use game_application::map::Map;
+    use game_application::vertex::Vertex;
+    use game_application::chunk::CHUNK_VOLUME;
+    use game_application::world_file::{read_chunk, write_header, read_header, WorldHeader, MAX_BLOCK_LENGTH};
+    use game_application::terrain::{HeightFieldTerrain, TerrainParameters, DEFAULT_TERRAIN};
+    use game_application::noise::{NoiseBasis, FractalKind};
+    use game_application::diamond_square::generate_height_field;
+
+    // Номер процесса в имени не дает одновременным запускам тестов писать в один файл
+    fn get_temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("voxel_craft_{}_{}.world", name, process::id()))
+    }
+
+    fn get_vertex_bits(vertex: &Vertex) -> Vec<u32> {
+        let mut bits: Vec<u32> = vertex.position.iter().chain(vertex.normal.iter()).chain(vertex.tex_coords.iter())
+            .map(|value| value.to_bits()).collect();
+        bits.push(vertex.material);
+        bits
+    }
+
+    fn get_sorted_triangles(map: &Map) -> Vec<Vec<Vec<u32>>> {
+        // Порядок вершин зависит от обхода HashMap, поэтому сравниваются отсортированные наборы треугольников
+        let (vertices, indices) = map.get_vertices();
+        let mut result: Vec<Vec<Vec<u32>>> = indices.chunks(3).map(|triangle| {
+            let mut triangle: Vec<Vec<u32>> = triangle.iter().map(|index| get_vertex_bits(&vertices[*index as usize])).collect();
+            triangle.sort();
+            triangle
+        }).collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn test_round_trip_meshes_identically() {
+        let path = get_temp_path("round_trip");
+        let mut map = Map::new(0.01, 65, Point3::new(0.0, 0.0, 0.0));
+        map.build_terrain(42, &HeightFieldTerrain::new(generate_height_field(65, 42), 65, 42, &DEFAULT_TERRAIN));
+        map.save(&path).unwrap();
+        let loaded = Map::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let expected = get_sorted_triangles(&map);
+        assert!(!expected.is_empty());
+        assert!(expected == get_sorted_triangles(&loaded));
+    }
+
+    #[test]
+    fn test_load_rejects_foreign_file() {
+        let path = get_temp_path("foreign");
+        fs::write(&path, b"not a world").unwrap();
+        let result = Map::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    fn write_terrain_header(terrain: TerrainParameters) -> Vec<u8> {
+        let header = WorldHeader {seed: 7, voxel_size: 0.01, map_size: 0, terrain, chunks_number: 0};
+        let mut data = Vec::new();
+        write_header(&mut data, &header).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_header_keeps_terrain_parameters() {
+        let mut terrain = DEFAULT_TERRAIN;
+        terrain.noise.basis = NoiseBasis::Simplex;
+        terrain.noise.fractal.kind = FractalKind::Ridged;
+        terrain.noise.fractal.gain = 0.25;
+        terrain.caves.overhangs = 0.25;
+        terrain.biomes = false;
+        terrain.erosion.talus = 2.5;
+        terrain.water.sea_level = Some(-3.5);
+        terrain.features.ruins = false;
+        terrain.ores[2].rarity = 0.125;
+        let data = write_terrain_header(terrain);
+        let loaded = read_header(&mut &data[..]).unwrap();
+        assert_eq!(format!("{:?}", loaded.terrain), format!("{:?}", terrain));
+    }
+
+    // Портит один параметр рельефа
+    type Spoil = fn(&mut TerrainParameters);
+
+    #[test]
+    fn test_read_rejects_invalid_terrain() {
+        let cases: Vec<(&str, Spoil)> = vec![
+            ("zero octaves", |terrain| terrain.noise.fractal.octaves = 0),
+            ("inverted cave depths", |terrain| terrain.caves.min_depth = terrain.caves.max_depth + 1.0),
+            ("NaN cave size", |terrain| terrain.caves.size = f64::NAN),
+            ("infinite cave depth", |terrain| terrain.caves.max_depth = f64::INFINITY),
+            ("negative tunnels", |terrain| terrain.caves.tunnels = -1.0),
+            ("caverns above one", |terrain| terrain.caves.caverns = 1.5),
+            ("negative caverns", |terrain| terrain.caves.caverns = -0.1),
+            ("negative overhangs", |terrain| terrain.caves.overhangs = -2.0),
+            ("erosion rate above one", |terrain| terrain.erosion.thermal_rate = 1.5),
+            ("infinite river width", |terrain| terrain.water.river_width = f64::INFINITY),
+            ("huge river width", |terrain| terrain.water.river_width = 1.0e6),
+            ("NaN river depth", |terrain| terrain.water.river_depth = f64::NAN),
+            ("huge river depth", |terrain| terrain.water.river_depth = 1.0e10),
+            ("NaN sea level", |terrain| terrain.water.sea_level = Some(f64::NAN)),
+            ("huge sea level", |terrain| terrain.water.sea_level = Some(1.0e12)),
+            ("inverted ore depths", |terrain| terrain.ores[0].min_depth = terrain.ores[0].max_depth + 1.0),
+            ("infinite rarity", |terrain| terrain.ores[1].rarity = f64::INFINITY),
+            ("rarity above maximum", |terrain| terrain.ores[1].rarity = 1.0e6),
+            ("NaN size", |terrain| terrain.ores[2].size = f64::NAN)
+        ];
+        for (name, spoil) in cases {
+            let mut terrain = DEFAULT_TERRAIN;
+            spoil(&mut terrain);
+            let data = write_terrain_header(terrain);
+            assert!(read_header(&mut &data[..]).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_read_rejects_oversized_chunk() {
+        // Длина блока больше любого возможного сжатого чанка
+        let mut data = vec![0; 12];
+        data.extend_from_slice(&((MAX_BLOCK_LENGTH + 1) as u32).to_le_bytes());
+        assert!(read_chunk(&mut &data[..]).is_err());
+        // Серии, которые вместе длиннее чанка
+        let runs = CHUNK_VOLUME / u16::MAX as usize + 1;
+        let mut data = vec![0; 12];
+        data.extend_from_slice(&((3 * runs) as u32).to_le_bytes());
+        for _ in 0..runs {
+            data.extend_from_slice(&u16::MAX.to_le_bytes());
+            data.push(0);
+        }
+        assert!(read_chunk(&mut &data[..]).is_err());
+    }
+}
@@ -1,21 +1,48 @@
 #[macro_use]
 extern crate glium;
+extern crate rand;
 
 mod game_application;
 use game_application::GameApplication;
 
+mod arguments;
+use arguments::{Arguments, Command};
+
 use std::env;
 
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() == 1 {
-        let mut game = GameApplication::new(".");
-        game.start_loop();
-    } else if args.len() == 2 {
-        let mut game = GameApplication::new(&args[1]);
-        game.start_loop();
-    } else {
-        panic!("Wrong arguments number");
+    let arguments = match Arguments::parse(&args) {
+        Ok(arguments) => arguments,
+        Err(message) => panic!("{}", message)
+    };
+    let mut game = GameApplication::new(&arguments.directory, arguments.settings);
+    match arguments.command {
+        Command::Play => {
+            if let Err(message) = game.start_loop() {
+                panic!("{}", message);
+            }
+        },
+        Command::Render{pose, size, output} => {
+            if let Err(message) = game.render(&pose, size, &output) {
+                panic!("{}", message);
+            }
+        },
+        Command::Export{pose, output} => {
+            if let Err(message) = game.export(&pose, &output) {
+                panic!("{}", message);
+            }
+        },
+        Command::Heightmap{output} => {
+            if let Err(message) = game.export_heightmap(&output) {
+                panic!("{}", message);
+            }
+        },
+        Command::Stats{pose} => {
+            if let Err(message) = game.report_materials(&pose) {
+                panic!("{}", message);
+            }
+        }
     }
 }
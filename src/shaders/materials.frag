@@ -0,0 +1,40 @@
+#version 150
+
+in vec3 v_normal;
+in vec3 v_position;
+flat in uint v_material;
+
+out vec4 color;
+
+uniform vec3 u_light;
+// Прозрачность материала известна только material.rs, поэтому непрозрачность задает проход отрисовки
+uniform float u_alpha;
+
+// Цвета в порядке texture_index из material.rs
+const vec3 material_colors[14] = vec3[14](
+    vec3(0.0, 0.0, 0.0),
+    vec3(0.1, 0.6, 0.1),
+    vec3(0.45, 0.3, 0.15),
+    vec3(0.5, 0.5, 0.5),
+    vec3(0.85, 0.8, 0.5),
+    vec3(0.1, 0.3, 0.8),
+    vec3(0.95, 0.95, 0.97),
+    vec3(0.4, 0.38, 0.36),
+    vec3(0.35, 0.22, 0.1),
+    vec3(0.15, 0.4, 0.12),
+    vec3(0.15, 0.15, 0.17),
+    vec3(0.12, 0.12, 0.12),
+    vec3(0.7, 0.5, 0.4),
+    vec3(0.9, 0.75, 0.2)
+);
+const vec3 specular_color = vec3(1.0, 1.0, 1.0);
+
+void main() {
+    vec3 diffuse_color = material_colors[v_material];
+    vec3 ambient_color = diffuse_color * 0.3;
+    float diffuse = max(dot(normalize(v_normal), normalize(u_light)), 0.0);
+    vec3 camera_dir = normalize(-v_position);
+    vec3 half_direction = normalize(normalize(u_light) + camera_dir);
+    float specular = pow(max(dot(half_direction, normalize(v_normal)), 0.0), 16.0);
+    color = vec4(ambient_color + diffuse * diffuse_color + 0.2 * specular * specular_color, u_alpha);
+}
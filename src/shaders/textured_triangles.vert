@@ -2,10 +2,12 @@
 in vec3 position;
 in vec3 normal;
 in vec2 tex_coords;
+in uint material;
 
 out vec3 v_normal;
 out vec3 v_position;
 out vec2 v_tex_coords;
+flat out uint v_material;
 
 uniform mat4 perspective;
 uniform mat4 view;
@@ -13,6 +15,7 @@ uniform mat4 model;
 
 void main() {
     v_tex_coords = tex_coords;
+    v_material = material;
     mat4 modelview = view * model;
     v_normal = transpose(inverse(mat3(modelview))) * normal;
     gl_Position = perspective * modelview * vec4(position, 1.0);